@@ -1,10 +1,18 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Months, NaiveDate, Utc};
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::errors::FocusError;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Child {
     Operation(Operation),
+    Temporal(Temporal),
+    Entity(Entity),
     Condition(Condition),
 }
 
@@ -14,6 +22,7 @@ pub enum Operand {
     //this is operator, of course, but rename would need to be coordinated with all the Lenses, EUCAIM providers, etc
     And,
     Or,
+    Not, // negates the conjunction of its children
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -29,7 +38,7 @@ pub enum ConditionType {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(untagged)]
+#[serde(untagged, try_from = "RawConditionValue")]
 pub enum ConditionValue {
     String(String),
     StringArray(Vec<String>),
@@ -39,16 +48,165 @@ pub enum ConditionValue {
     DateRange(DateRange),
 }
 
+/// Condition value as sent, dates are parsed when converting it to a [`ConditionValue`]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawConditionValue {
+    String(String),
+    StringArray(Vec<String>),
+    Boolean(bool),
+    Number(f64),
+    NumRange(NumRange),
+    DateRange(RawDateRange),
+}
+
+/// Date range as sent, with at least one of its ends
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDateRange {
+    #[serde(default)]
+    min: Option<String>,
+    #[serde(default)]
+    max: Option<String>,
+}
+
+impl TryFrom<RawConditionValue> for ConditionValue {
+    type Error = FocusError;
+
+    fn try_from(raw: RawConditionValue) -> Result<Self, Self::Error> {
+        // Lens sends an empty string for a missing end
+        let parse = |date: Option<String>| {
+            date.filter(|date| !date.is_empty())
+                .map(|date| date.parse())
+                .transpose()
+        };
+        Ok(match raw {
+            RawConditionValue::String(string) => ConditionValue::String(string),
+            RawConditionValue::StringArray(string_array) => {
+                ConditionValue::StringArray(string_array)
+            }
+            RawConditionValue::Boolean(boolean) => ConditionValue::Boolean(boolean),
+            RawConditionValue::Number(number) => ConditionValue::Number(number),
+            RawConditionValue::NumRange(num_range) => ConditionValue::NumRange(num_range),
+            RawConditionValue::DateRange(RawDateRange {
+                min: None,
+                max: None,
+            }) => {
+                return Err(FocusError::AstInvalid(
+                    "Date range needs a min or a max".into(),
+                ))
+            }
+            RawConditionValue::DateRange(RawDateRange { min, max }) => {
+                ConditionValue::DateRange(DateRange {
+                    min: parse(min)?,
+                    max: parse(max)?,
+                })
+            }
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NumRange {
     pub min: f64,
     pub max: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Dates in a range are inclusive, a missing end leaves the range open
+#[derive(Serialize, Debug, Clone)]
 pub struct DateRange {
-    pub min: String, // we don't parse dates yet
-    pub max: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<AstDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<AstDate>,
+}
+
+/// A date as precise as stated in the AST
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum AstDate {
+    Year(i32),
+    Month(i32, u32),
+    Day(NaiveDate),
+}
+
+impl AstDate {
+    pub fn first_day(&self) -> NaiveDate {
+        match *self {
+            AstDate::Year(year) => NaiveDate::from_ymd_opt(year, 1, 1),
+            AstDate::Month(year, month) => NaiveDate::from_ymd_opt(year, month, 1),
+            AstDate::Day(day) => Some(day),
+        }
+        .expect("checked when parsing")
+    }
+
+    pub fn last_day(&self) -> NaiveDate {
+        match *self {
+            AstDate::Year(year) => NaiveDate::from_ymd_opt(year, 12, 31),
+            AstDate::Month(year, month) => NaiveDate::from_ymd_opt(year, month, 1)
+                .and_then(|first| first.checked_add_months(Months::new(1)))
+                .and_then(|next| next.pred_opt()),
+            AstDate::Day(day) => Some(day),
+        }
+        .expect("checked when parsing")
+    }
+}
+
+impl FromStr for AstDate {
+    type Err = FocusError;
+
+    /// Accepts years, months and days in ISO 8601, and RFC 3339 timestamps, which are cut to the day in UTC
+    fn from_str(date: &str) -> Result<Self, Self::Err> {
+        let invalid = || FocusError::AstInvalidDateFormat(date.to_string());
+
+        if let Ok(datetime) = date.parse::<DateTime<Utc>>() {
+            return Ok(AstDate::Day(datetime.date_naive()));
+        }
+        if let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            return Ok(AstDate::Day(day));
+        }
+
+        let number = |digits: &str, len: usize| {
+            (digits.len() == len && digits.chars().all(|c| c.is_ascii_digit()))
+                .then(|| digits.parse::<u32>().ok())
+                .flatten()
+                .ok_or_else(invalid)
+        };
+        let parsed = match date.split('-').collect::<Vec<_>>().as_slice() {
+            [year] => AstDate::Year(number(year, 4)? as i32),
+            [year, month] => AstDate::Month(number(year, 4)? as i32, number(month, 2)?),
+            _ => return Err(invalid()),
+        };
+        // rejects months like 13, and years outside chrono's range
+        if let AstDate::Month(year, month) = parsed {
+            NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(invalid)?;
+        }
+        Ok(parsed)
+    }
+}
+
+impl TryFrom<String> for AstDate {
+    type Error = FocusError;
+
+    fn try_from(date: String) -> Result<Self, Self::Error> {
+        date.parse()
+    }
+}
+
+impl fmt::Display for AstDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AstDate::Year(year) => write!(f, "{:04}", year),
+            AstDate::Month(year, month) => write!(f, "{:04}-{:02}", year, month),
+            AstDate::Day(day) => write!(f, "{}", day.format("%Y-%m-%d")),
+        }
+    }
+}
+
+impl From<AstDate> for String {
+    fn from(date: AstDate) -> Self {
+        date.to_string()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -63,6 +221,152 @@ pub struct Condition {
     pub key: String,
     pub type_: ConditionType,
     pub value: ConditionValue,
+    // UCUM unit of numeric values, if not in the unit the criterion is measured in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    // also match the descendants of the codes, as far as there is a hierarchy of their code system
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub descendants: bool,
+    // at least this many resources have to match, instead of just one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TemporalRelation {
+    Before,
+    After,
+    Within, // before or after
+}
+
+/// Requires the event to happen in the given relation to the anchor, both described by a condition
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Temporal {
+    pub relation: TemporalRelation,
+    // upper bound of the distance between the events, mandatory for WITHIN
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<u32>,
+    pub anchor: Condition,
+    pub event: Condition,
+}
+
+/// Requires all conditions to hold for the same resource, e.g. the same specimen
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entity {
+    pub entity: String,
+    pub children: Vec<Condition>,
+}
+
+impl Operation {
+    /// Flattens nested operations, drops empty groups, removes duplicate children and sorts them canonically, without changing the meaning
+    pub fn normalize(self) -> Operation {
+        let mut children = Vec::new();
+        for child in self.children {
+            match child.normalize() {
+                // AND in AND, OR in OR, and any single criterion group
+                Child::Operation(operation)
+                    if operation.operand != Operand::Not
+                        && (operation.operand == self.operand || operation.children.len() == 1) =>
+                {
+                    children.extend(operation.children)
+                }
+                // empty groups are ignored anyway, but NOT would be left without children
+                Child::Operation(operation)
+                    if operation.operand != Operand::Not
+                        && operation.children.is_empty()
+                        && self.operand != Operand::Not => {}
+                child => children.push(child),
+            }
+        }
+        let mut children = canonical(children);
+
+        // a single group needs no group around it
+        if self.operand != Operand::Not {
+            if let [Child::Operation(_)] = children.as_slice() {
+                if let Some(Child::Operation(operation)) = children.pop() {
+                    return operation;
+                }
+            }
+        }
+        Operation {
+            operand: self.operand,
+            children,
+        }
+    }
+}
+
+impl Operation {
+    /// Nests a normalized conjunction of criteria the way Lens does, as an OR of an AND of single criterion ORs,
+    /// for the query builders that expect exactly that; other operations stay as they are
+    pub fn lens_nesting(self) -> Operation {
+        let conjunction = match self.operand {
+            Operand::And => true,
+            Operand::Or => self.children.len() == 1,
+            Operand::Not => false,
+        };
+        if !conjunction
+            || self.children.is_empty()
+            || !self
+                .children
+                .iter()
+                .all(|child| matches!(child, Child::Condition(_)))
+        {
+            return self;
+        }
+        let groups = self
+            .children
+            .into_iter()
+            .map(|child| {
+                Child::Operation(Operation {
+                    operand: Operand::Or,
+                    children: vec![child],
+                })
+            })
+            .collect();
+        Operation {
+            operand: Operand::Or,
+            children: vec![Child::Operation(Operation {
+                operand: Operand::And,
+                children: groups,
+            })],
+        }
+    }
+}
+
+impl Child {
+    /// AND and OR groups without any criterion, also nested ones, which are ignored and match everything
+    pub fn is_empty_group(&self) -> bool {
+        match self {
+            Child::Operation(operation) => {
+                operation.operand != Operand::Not
+                    && operation.children.iter().all(Child::is_empty_group)
+            }
+            _ => false,
+        }
+    }
+
+    fn normalize(self) -> Child {
+        match self {
+            Child::Operation(operation) => Child::Operation(operation.normalize()),
+            Child::Entity(entity) => Child::Entity(Entity {
+                entity: entity.entity,
+                children: canonical(entity.children),
+            }),
+            child => child,
+        }
+    }
+}
+
+/// Sorts by the JSON representation and drops duplicates, so that equivalent ASTs look the same
+fn canonical<T: Serialize>(items: Vec<T>) -> Vec<T> {
+    let mut keyed: Vec<(String, T)> = items
+        .into_iter()
+        .map(|item| (serde_json::to_string(&item).unwrap_or_default(), item))
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    keyed.dedup_by(|(a, _), (b, _)| a == b);
+    keyed.into_iter().map(|(_, item)| item).collect()
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -77,6 +381,8 @@ mod test {
 
     const EQUALS_AST: &str = r#"{"ast":{"operand":"AND","children":[{"key":"age","type":"EQUALS","value":5.0}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
 
+    const RADIOTHERAPY_AFTER_DIAGNOSIS: &str = r#"{"ast":{"operand":"AND","children":[{"relation":"AFTER","days":180,"anchor":{"key":"diagnosis","type":"EQUALS","value":"C34.0"},"event":{"key":"procedure","type":"EQUALS","value":"ST"}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
     #[test]
     fn test_deserialize_ast() {
         let ast_variable: Ast =
@@ -86,4 +392,84 @@ mod test {
 
         assert_eq!(EQUALS_AST, ast_string);
     }
+
+    const LENS_NESTED: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"AND","children":[{"operand":"OR","children":[{"key":"gender","type":"EQUALS","value":"male"}]},{"operand":"OR","children":[]},{"operand":"AND","children":[{"key":"diagnosis","type":"EQUALS","value":"C50"},{"operand":"OR","children":[{"key":"sample_kind","type":"EQUALS","value":"blood-serum"},{"key":"sample_kind","type":"EQUALS","value":"blood-plasma"},{"key":"sample_kind","type":"EQUALS","value":"blood-serum"}]}]},{"operand":"OR","children":[{"key":"gender","type":"EQUALS","value":"male"}]},{"operand":"NOT","children":[{"operand":"OR","children":[]}]}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const LENS_NESTED_NORMALIZED: &str = r#"{"operand":"AND","children":[{"key":"diagnosis","type":"EQUALS","value":"C50"},{"key":"gender","type":"EQUALS","value":"male"},{"operand":"NOT","children":[{"operand":"OR","children":[]}]},{"operand":"OR","children":[{"key":"sample_kind","type":"EQUALS","value":"blood-plasma"},{"key":"sample_kind","type":"EQUALS","value":"blood-serum"}]}]}"#;
+
+    #[test]
+    fn test_normalize() {
+        let ast_variable: Ast =
+            serde_json::from_str(LENS_NESTED).expect("Failed to deserialize JSON");
+
+        let normalized = ast_variable.ast.normalize();
+
+        assert_eq!(
+            LENS_NESTED_NORMALIZED,
+            serde_json::to_string(&normalized).expect("Failed to serialize JSON")
+        );
+        // normalizing again changes nothing
+        assert_eq!(
+            LENS_NESTED_NORMALIZED,
+            serde_json::to_string(&normalized.normalize()).expect("Failed to serialize JSON")
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!("2020".parse::<AstDate>().unwrap(), AstDate::Year(2020));
+        assert_eq!(
+            "2020-02".parse::<AstDate>().unwrap(),
+            AstDate::Month(2020, 2)
+        );
+        assert_eq!(
+            "2020-02-03T23:30:00Z".parse::<AstDate>().unwrap(),
+            AstDate::Day(NaiveDate::from_ymd_opt(2020, 2, 3).unwrap())
+        );
+        assert_eq!(
+            "2020-02".parse::<AstDate>().unwrap().last_day(),
+            NaiveDate::from_ymd_opt(2020, 2, 29).unwrap()
+        );
+        for invalid in ["20", "2020-13", "2020-02-30", "+202", "yesterday"] {
+            assert!(matches!(
+                invalid.parse::<AstDate>(),
+                Err(FocusError::AstInvalidDateFormat(_))
+            ));
+        }
+
+        let value: ConditionValue = serde_json::from_str(r#"{"min":"2020-01","max":""}"#).unwrap();
+        let ConditionValue::DateRange(range) = value else {
+            panic!("Expected a date range");
+        };
+        assert_eq!(range.min, Some(AstDate::Month(2020, 1)));
+        assert_eq!(range.max, None);
+
+        let error =
+            serde_json::from_str::<ConditionValue>(r#"{"min":"2020-01","max":"2020-31-12"}"#)
+                .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Invalid date format: 2020-31-12"));
+
+        // objects without either end or with other fields are no date ranges
+        assert!(serde_json::from_str::<ConditionValue>("{}").is_err());
+        assert!(serde_json::from_str::<ConditionValue>(r#"{"foo":1}"#).is_err());
+        assert!(serde_json::from_str::<ConditionValue>(r#"{"min":"2020","foo":1}"#).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_temporal() {
+        let ast_variable: Ast =
+            serde_json::from_str(RADIOTHERAPY_AFTER_DIAGNOSIS).expect("Failed to deserialize JSON");
+
+        let Child::Temporal(temporal) = &ast_variable.ast.children[0] else {
+            panic!("Expected a temporal relation");
+        };
+        assert_eq!(temporal.relation, TemporalRelation::After);
+        assert_eq!(temporal.days, Some(180));
+
+        let ast_string = serde_json::to_string(&ast_variable).expect("Failed to serialize JSON");
+
+        assert_eq!(RADIOTHERAPY_AFTER_DIAGNOSIS, ast_string);
+    }
 }
@@ -7,8 +7,8 @@ use tracing::{debug, info, warn};
 use crate::ast;
 use crate::config::CONFIG;
 use crate::errors::FocusError;
+use crate::measure_period::MeasurePeriod;
 use crate::util;
-use crate::util::get_json_field;
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "lang", rename_all = "lowercase")]
@@ -55,57 +55,74 @@ pub async fn check_availability() -> bool {
     false
 }
 
-pub async fn post_library(library: String) -> Result<(), FocusError> {
+/// Creates the Library unless one with the canonical URL exists
+pub async fn post_library(library: String, url: &str) -> Result<(), FocusError> {
     debug!("Creating a Library...");
 
     let resp = CONFIG
         .client
         .post(format!("{}Library", CONFIG.endpoint_url))
         .header("Content-Type", "application/json")
+        .header("If-None-Exist", format!("url={url}"))
         .body(library)
         .send()
         .await
         .map_err(FocusError::UnableToPostLibrary)?;
 
-    if resp.status() == StatusCode::CREATED {
+    if resp
+        .error_for_status()
+        .map_err(FocusError::UnableToPostLibrary)?
+        .status()
+        == StatusCode::CREATED
+    {
         debug!("Successfully created a Library");
     } else {
-        let error_message = format!("Error while creating a Library: {}", resp.status());
-        warn!("{}", error_message);
+        debug!("Reusing the Library with canonical URL: {}", url);
     }
 
     Ok(())
 }
 
-pub async fn post_measure(measure: String) -> Result<(), FocusError> {
+/// Creates the Measure unless one with the canonical URL exists
+pub async fn post_measure(measure: String, url: &str) -> Result<(), FocusError> {
     debug!("Creating a Measure...");
     let resp = CONFIG
         .client
         .post(format!("{}Measure", CONFIG.endpoint_url))
         .header("Content-Type", "application/json")
+        .header("If-None-Exist", format!("url={url}"))
         .body(measure)
         .send()
         .await
         .map_err(FocusError::UnableToPostMeasure)?;
 
-    if resp.status() == StatusCode::CREATED {
+    if resp
+        .error_for_status()
+        .map_err(FocusError::UnableToPostMeasure)?
+        .status()
+        == StatusCode::CREATED
+    {
         debug!("Successfully created a Measure");
     } else {
-        let error_message = format!("Error while creating a Measure: {}", resp.status());
-        warn!("{}", error_message);
+        debug!("Reusing the Measure with canonical URL: {}", url);
     }
 
     Ok(())
 }
 
-pub async fn evaluate_measure(url: String) -> Result<String, FocusError> {
-    debug!("Evaluating the Measure with canonical URL: {}", url);
+pub async fn evaluate_measure(url: String, period: &MeasurePeriod) -> Result<String, FocusError> {
+    debug!(
+        "Evaluating the Measure with canonical URL {} for the period {}",
+        url, period
+    );
     let resp = CONFIG
         .client
-        .get(format!(
-            "{}Measure/$evaluate-measure?measure={}&periodStart=2000&periodEnd=2030",
-            CONFIG.endpoint_url, url
-        ))
+        .get(format!("{}Measure/$evaluate-measure", CONFIG.endpoint_url))
+        .query(&[
+            ("measure", url.as_str()),
+            ("periodStart", period.start.as_str()),
+            ("periodEnd", period.end.as_str()),
+        ])
         .send()
         .await
         .map_err(FocusError::MeasureEvaluationErrorReqwest)?;
@@ -118,6 +135,8 @@ pub async fn evaluate_measure(url: String) -> Result<String, FocusError> {
         resp.text()
             .await
             .map_err(FocusError::MeasureEvaluationErrorReqwest)
+    } else if resp.status() == StatusCode::NOT_FOUND {
+        Err(FocusError::MeasureNotFound(url))
     } else {
         warn!(
             "Error while evaluating the Measure with canonical URL `{}`: {:?}",
@@ -130,19 +149,6 @@ pub async fn evaluate_measure(url: String) -> Result<String, FocusError> {
     }
 }
 
-pub async fn run_cql_query(library: &Value, measure: &Value) -> Result<String, FocusError> {
-    let url: String = if let Ok(value) = get_json_field(&measure.to_string(), "url") {
-        value.to_string().replace('"', "")
-    } else {
-        return Err(FocusError::CQLQueryError);
-    };
-    debug!("Evaluating the Measure with canonical URL: {}", url);
-
-    post_library(library.to_string()).await?; //TODO make it with into or could change the function signature to take the library
-    post_measure(measure.to_string()).await?; //ditto   &str
-    evaluate_measure(url).await
-}
-
 pub fn parse_blaze_query_payload_ast(ast_query: &str) -> Result<ast::Ast, FocusError> {
     let decoded = util::base64_decode(ast_query)?;
     Ok(serde_json::from_slice(&decoded)?)
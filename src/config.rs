@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::path::PathBuf;
 
@@ -9,6 +10,10 @@ use reqwest::{Certificate, Client, Proxy};
 use tracing::{debug, info, warn};
 
 use crate::errors::FocusError;
+use crate::measure_period::{parse_project_periods, MeasurePeriod};
+use crate::obfuscation::{
+    read_project_params, ObfuscationArgs, ObfuscationParams, ObfuscationScope,
+};
 
 #[derive(clap::ValueEnum, Clone, PartialEq, Debug)]
 pub enum Obfuscate {
@@ -19,7 +24,8 @@ pub enum Obfuscate {
 #[derive(clap::ValueEnum, Clone, PartialEq, Debug, Copy)]
 pub enum EndpointType {
     Blaze,
-    Omop,      // endpoint is URL of a query mediator translating AST to provider specific SQL
+    Fhir, // endpoint is URL of a FHIR R4 server other than Blaze implementing $evaluate-measure and $cql
+    Omop, // endpoint is URL of a query mediator translating AST to provider specific SQL
     EucaimApi, // endpoint is URL of custom API for querying EUCAIM provider
     #[cfg(feature = "query-sql")]
     EucaimSql,
@@ -33,6 +39,7 @@ impl fmt::Display for EndpointType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             EndpointType::Blaze => write!(f, "blaze"),
+            EndpointType::Fhir => write!(f, "fhir"),
             EndpointType::Omop => write!(f, "omop"),
             EndpointType::EucaimApi => write!(f, "eucaim_api"),
             #[cfg(feature = "query-sql")]
@@ -91,7 +98,7 @@ struct CliArgs {
     #[clap(long, env, value_parser)]
     exporter_url: Option<Url>,
 
-    /// Type of the endpoint, e.g. "blaze", "omop"
+    /// Type of the endpoint, e.g. "blaze", "fhir", "omop"
     #[clap(long, env, value_parser = clap::value_parser!(EndpointType), default_value = "blaze")]
     endpoint_type: EndpointType,
 
@@ -99,49 +106,44 @@ struct CliArgs {
     #[clap(long, env, value_parser, value_delimiter = ',')]
     cql_projects_enabled: Option<Vec<String>>,
 
-    /// Should the results be obfuscated
-    #[clap(long, env, value_parser = clap::value_parser!(Obfuscate), default_value = "yes")]
-    obfuscate: Obfuscate,
-
-    /// Should zero values be obfuscated - default false
+    /// Directory with a subdirectory per project defining it like the compiled-in ones, with project.json, template.cql and body.json; these take precedence over compiled-in projects of the same name
     #[clap(long, env, value_parser)]
-    obfuscate_zero: bool,
+    projects_dir: Option<PathBuf>,
 
-    /// The mode of obfuscating values below 10: 0 - return zero, 1 - return ten, 2 - obfuscate using Laplace distribution and rounding
-    #[clap(long, env, value_parser, default_value = "1")]
-    obfuscate_below_10_mode: usize,
+    /// Directory with JSON files of code hierarchies, e.g. of ICD-10, used for conditions asking for the descendants of their codes
+    #[clap(long, env, value_parser)]
+    terminology_dir: Option<PathBuf>,
 
-    /// Sensitivity parameter for obfuscating the counts in the Patient stratifier
-    #[clap(long, env, value_parser, default_value = "1.")]
-    delta_patient: f64,
+    /// Comma separated list of Beam app IDs allowed to have queries explained, i.e. get the generated query back instead of its result
+    #[clap(long, env, value_parser, value_delimiter = ',')]
+    explain_requesters: Option<Vec<String>>,
 
-    /// Sensitivity parameter for obfuscating the counts in the Specimen stratifier
-    #[clap(long, env, value_parser, default_value = "20.")]
-    delta_specimen: f64,
+    /// Days after which Libraries and Measures Focus posted to Blaze or another FHIR server are deleted again, they are kept if not set
+    #[clap(long, env, value_parser)]
+    resource_max_age_days: Option<u32>,
 
-    /// Sensitivity parameter for obfuscating the counts in the Diagnosis stratifier
-    #[clap(long, env, value_parser, default_value = "3.")]
-    delta_diagnosis: f64,
+    /// Period Blaze evaluates Measures for, as FHIR dates separated by /, e.g. 1900/2030
+    #[clap(long, env, value_parser, default_value = "2000/2030")]
+    measure_period: String,
 
-    /// Sensitivity parameter for obfuscating the counts in the Procedure stratifier
-    #[clap(long, env, value_parser, default_value = "1.7")]
-    delta_procedures: f64,
+    /// Comma separated list of evaluation periods of projects differing from the global one, e.g. dktk=1900/2030
+    #[clap(long, env, value_parser, value_delimiter = ',')]
+    project_measure_periods: Option<Vec<String>>,
 
-    /// Sensitivity parameter for obfuscating the counts in the Medication Statements stratifier
-    #[clap(long, env, value_parser, default_value = "2.1")]
-    delta_medication_statements: f64,
+    /// Comma separated list of Beam app IDs allowed to set the evaluation period of their queries in the task metadata
+    #[clap(long, env, value_parser, value_delimiter = ',')]
+    measure_period_requesters: Option<Vec<String>>,
 
-    /// Sensitivity parameter for obfuscating the counts in the Histo stratifier
-    #[clap(long, env, value_parser, default_value = "20.")]
-    delta_histo: f64,
+    /// Should the results be obfuscated
+    #[clap(long, env, value_parser = clap::value_parser!(Obfuscate), default_value = "yes")]
+    obfuscate: Obfuscate,
 
-    /// Privacy budget parameter for obfuscating the counts in the stratifiers
-    #[clap(long, env, value_parser, default_value = "0.28")]
-    epsilon: f64,
+    #[clap(flatten)]
+    obfuscation: ObfuscationArgs,
 
-    /// The granularity of the rounding of the obfuscated values
-    #[clap(long, env, value_parser, default_value = "10")]
-    rounding_step: usize,
+    /// The path to a JSON file with obfuscation parameters per project, e.g. {"bbmri": {"preset": "strict", "rounding_step": 5}}; parameters not set there default to the global ones
+    #[clap(long, env, value_parser)]
+    project_obfuscation_file: Option<PathBuf>,
 
     /// Projects for which the results are not to be obfuscated, separated by ;
     #[clap(
@@ -197,17 +199,16 @@ pub(crate) struct Config {
     pub exporter_url: Option<Url>,
     pub endpoint_type: EndpointType,
     pub cql_projects_enabled: Option<Vec<String>>,
+    pub explain_requesters: Option<Vec<String>>,
+    pub projects_dir: Option<PathBuf>,
+    pub terminology_dir: Option<PathBuf>,
+    pub resource_max_age_days: Option<u32>,
+    pub measure_period: MeasurePeriod,
+    pub project_measure_periods: HashMap<String, MeasurePeriod>,
+    pub measure_period_requesters: Option<Vec<String>>,
     pub obfuscate: Obfuscate,
-    pub obfuscate_zero: bool,
-    pub obfuscate_below_10_mode: usize,
-    pub delta_patient: f64,
-    pub delta_specimen: f64,
-    pub delta_diagnosis: f64,
-    pub delta_procedures: f64,
-    pub delta_medication_statements: f64,
-    pub delta_histo: f64,
-    pub epsilon: f64,
-    pub rounding_step: usize,
+    pub obfuscation: ObfuscationParams,
+    pub project_obfuscation: HashMap<String, ObfuscationParams>,
     pub unobfuscated: Vec<String>,
     pub queries_to_cache: Option<PathBuf>,
     pub client: Client,
@@ -234,6 +235,18 @@ impl Config {
                 ))
             })?;
         let client = prepare_reqwest_client(&tls_ca_certificates)?;
+        let obfuscation = cli_args.obfuscation.into_params()?;
+        let project_obfuscation = match cli_args.project_obfuscation_file {
+            Some(path) => read_project_params(&path, &obfuscation)?,
+            None => HashMap::new(),
+        };
+        let measure_period = cli_args.measure_period.parse()?;
+        let project_measure_periods = parse_project_periods(
+            cli_args
+                .project_measure_periods
+                .as_deref()
+                .unwrap_or_default(),
+        )?;
         dbg!(cli_args.endpoint_url.clone());
         dbg!(cli_args.blaze_url.clone());
         let config = Config {
@@ -251,17 +264,16 @@ impl Config {
             exporter_url: cli_args.exporter_url,
             endpoint_type: cli_args.endpoint_type,
             cql_projects_enabled: cli_args.cql_projects_enabled,
+            explain_requesters: cli_args.explain_requesters,
+            projects_dir: cli_args.projects_dir,
+            terminology_dir: cli_args.terminology_dir,
+            resource_max_age_days: cli_args.resource_max_age_days,
+            measure_period,
+            project_measure_periods,
+            measure_period_requesters: cli_args.measure_period_requesters,
             obfuscate: cli_args.obfuscate,
-            obfuscate_zero: cli_args.obfuscate_zero,
-            obfuscate_below_10_mode: cli_args.obfuscate_below_10_mode,
-            delta_patient: cli_args.delta_patient,
-            delta_specimen: cli_args.delta_specimen,
-            delta_diagnosis: cli_args.delta_diagnosis,
-            delta_procedures: cli_args.delta_procedures,
-            delta_medication_statements: cli_args.delta_medication_statements,
-            delta_histo: cli_args.delta_histo,
-            epsilon: cli_args.epsilon,
-            rounding_step: cli_args.rounding_step,
+            obfuscation,
+            project_obfuscation,
             unobfuscated: cli_args.projects_no_obfuscation.split(';').map(|s| s.to_string()).collect(),
             queries_to_cache: cli_args.queries_to_cache,
             provider: cli_args.provider,
@@ -276,6 +288,32 @@ impl Config {
         };
         Ok(config)
     }
+
+    /// Period the Measures of the project are evaluated for, its own if it has one, otherwise the global one
+    pub fn measure_period(&self, project: &str) -> &MeasurePeriod {
+        self.project_measure_periods
+            .get(project)
+            .unwrap_or(&self.measure_period)
+    }
+
+    /// Projects with their own obfuscation parameters are obfuscated in their own scope, all others in the global one
+    pub fn obfuscation_scope(&self, project: &str) -> ObfuscationScope {
+        if self.project_obfuscation.contains_key(project) {
+            ObfuscationScope::Project(project.to_string())
+        } else {
+            ObfuscationScope::Global
+        }
+    }
+
+    pub fn obfuscation_params(&self, scope: &ObfuscationScope) -> &ObfuscationParams {
+        match scope {
+            ObfuscationScope::Project(project) => self
+                .project_obfuscation
+                .get(project)
+                .unwrap_or(&self.obfuscation),
+            ObfuscationScope::Global => &self.obfuscation,
+        }
+    }
 }
 
 pub fn load_certificates_from_dir(
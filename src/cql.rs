@@ -1,17 +1,34 @@
 use crate::ast;
 use crate::errors::FocusError;
-use crate::projects::{CriterionRole, Project};
+use crate::projects::{self, CriterionRole, Project};
+use crate::{cql_syntax, terminology, units, validation};
 
 use base64::{prelude::BASE64_STANDARD as BASE64, Engine as _};
-use chrono::offset::Utc;
-use chrono::{DateTime, NaiveDate, NaiveTime};
-use indexmap::set::IndexSet;
-use tracing::info;
+use chrono::NaiveDate;
+use indexmap::IndexMap;
 use uuid::Uuid;
 
-pub fn generate_body(ast: ast::Ast, project: Project) -> Result<String, FocusError> {
-    Ok(project
-        .get_body()
+/// Generates the Blaze query body for the AST, with only the requested stratifiers if there is a selection
+pub fn generate_body(
+    ast: ast::Ast,
+    project: Project,
+    stratifiers: Option<&[String]>,
+) -> Result<String, FocusError> {
+    let ast = validation::validated(ast, Some(&project))?;
+    let ast = ast::Ast {
+        ast: terminology::expand(ast.ast, &project, terminology::hierarchies()),
+        id: ast.id,
+    };
+
+    let body = project.get_body().to_string();
+    let cql = generate_cql(ast, project)?;
+    let (body, cql) = match stratifiers {
+        Some(stratifiers) => select_stratifiers(&body, &cql, stratifiers)?,
+        None => (body, cql),
+    };
+    cql_syntax::ensure_valid(&cql)?;
+
+    Ok(body
         .replace(
             "{{LIBRARY_UUID}}",
             format!("urn:uuid:{}", Uuid::new_v4()).as_str(),
@@ -20,10 +37,126 @@ pub fn generate_body(ast: ast::Ast, project: Project) -> Result<String, FocusErr
             "{{MEASURE_UUID}}",
             format!("urn:uuid:{}", Uuid::new_v4()).as_str(),
         )
-        .replace(
-            "{{LIBRARY_ENCODED}}",
-            BASE64.encode(generate_cql(ast, project)?).as_str(),
-        ))
+        .replace("{{LIBRARY_ENCODED}}", BASE64.encode(cql).as_str()))
+}
+
+/// Keeps only the requested stratifiers in the Measure and drops the CQL definitions nothing else uses anymore.
+/// Groups are kept if they or one of their stratifiers are requested by name, the first group with the patient count always
+fn select_stratifiers(
+    body: &str,
+    cql: &str,
+    stratifiers: &[String],
+) -> Result<(String, String), FocusError> {
+    let mut body: serde_json::Value = serde_json::from_str(body)?;
+    let name = |element: &serde_json::Value| element["code"]["text"].as_str().map(String::from);
+    let expression =
+        |element: &serde_json::Value| element["criteria"]["expression"].as_str().map(String::from);
+
+    let Some(groups) = body["measure"]["group"].as_array_mut() else {
+        return Err(FocusError::ParsingError(
+            "Body has no Measure groups".into(),
+        ));
+    };
+    let known: Vec<String> = groups
+        .iter()
+        .chain(
+            groups
+                .iter()
+                .flat_map(|group| group["stratifier"].as_array().into_iter().flatten()),
+        )
+        .filter_map(name)
+        .collect();
+    if let Some(unknown) = stratifiers.iter().find(|s| !known.contains(s)) {
+        return Err(FocusError::UnknownStratifier(unknown.clone()));
+    }
+
+    let mut unused_expressions = Vec::new();
+    let mut index = 0;
+    groups.retain_mut(|group| {
+        let requested = |element: &serde_json::Value| {
+            name(element).is_some_and(|name| stratifiers.contains(&name))
+        };
+        let keep = index == 0
+            || requested(group)
+            || group["stratifier"]
+                .as_array()
+                .is_some_and(|stratifiers| stratifiers.iter().any(requested));
+        index += 1;
+
+        if let Some(group_stratifiers) = group["stratifier"].as_array_mut() {
+            group_stratifiers.retain(|stratifier| {
+                let keep_stratifier = keep && requested(stratifier);
+                if !keep_stratifier {
+                    unused_expressions.extend(expression(stratifier));
+                }
+                keep_stratifier
+            });
+        }
+        if !keep {
+            for population in group["population"].as_array().into_iter().flatten() {
+                unused_expressions.extend(expression(population));
+            }
+        }
+        keep
+    });
+    let body = serde_json::to_string_pretty(&body)?;
+
+    // a definition can only go if neither the Measure nor another remaining definition refers to it
+    let mut blocks: Vec<&str> = Vec::new();
+    let mut start = 0;
+    for (offset, _) in cql.match_indices("\ndefine ") {
+        blocks.push(&cql[start..offset + 1]);
+        start = offset + 1;
+    }
+    blocks.push(&cql[start..]);
+    // definitions only the removed ones referred to are unused as well, until nothing else goes
+    let mut candidates = unused_expressions;
+    loop {
+        let unused: Vec<String> = candidates
+            .into_iter()
+            .filter(|expression| {
+                !refers_to(&body, expression)
+                    && blocks.iter().all(|block| {
+                        defined(block).is_some_and(|defined| &defined == expression)
+                            || !refers_to(block, expression)
+                    })
+            })
+            .collect();
+        if unused.is_empty() {
+            break;
+        }
+        let (removed, kept): (Vec<&str>, Vec<&str>) = blocks
+            .into_iter()
+            .partition(|block| defined(block).is_some_and(|defined| unused.contains(&defined)));
+        candidates = kept
+            .iter()
+            .filter_map(|block| defined(block))
+            .filter(|defined| removed.iter().any(|block| refers_to(block, defined)))
+            .collect();
+        blocks = kept;
+    }
+
+    Ok((body, blocks.concat()))
+}
+
+/// Name of the definition a block of CQL starts with, without the quotes of names like `"Foo Bar"`
+fn defined(block: &str) -> Option<String> {
+    let rest = block.strip_prefix("define ")?;
+    let rest = rest.strip_prefix("function ").unwrap_or(rest);
+    match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next(),
+        None => rest.split([':', '(', ' ']).next(),
+    }
+    .map(String::from)
+}
+
+/// Whether the text mentions the identifier as a whole word
+fn refers_to(text: &str, identifier: &str) -> bool {
+    let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(identifier).any(|(offset, _)| {
+        !text[..offset].ends_with(is_identifier_char)
+            && !text[offset + identifier.len()..].starts_with(is_identifier_char)
+    })
 }
 
 fn generate_cql(ast: ast::Ast, project: Project) -> Result<String, FocusError> {
@@ -36,34 +169,53 @@ fn generate_cql(ast: ast::Ast, project: Project) -> Result<String, FocusError> {
     let mut cql = project.get_cql_template().to_string();
 
     let operator_str = match ast.ast.operand {
-        ast::Operand::And => " and ",
+        ast::Operand::And | ast::Operand::Not => " and ",
         ast::Operand::Or => " or ",
     };
 
-    let mut mandatory_codes = project.get_mandatory_code_lists().clone();
+    // empty groups match everything and are left out, they would be empty parentheses otherwise
+    let children: Vec<&ast::Child> = ast
+        .ast
+        .children
+        .iter()
+        .filter(|child| !child.is_empty_group())
+        .collect();
+    let negated = ast.ast.operand == ast::Operand::Not;
+    if negated && children.is_empty() {
+        return Err(FocusError::AstOperatorValueMismatch(
+            "Operator NOT needs at least one criterion".into(),
+        ));
+    }
+    let mut negated_filter_criteria = String::new(); // specimens are not filtered by negated criteria
+
+    let mut code_systems = CodeSystems::default();
+    code_systems.declare_mandatory(&project)?;
 
-    for (index, grandchild) in ast.ast.children.iter().enumerate() {
+    for (index, grandchild) in children.iter().enumerate() {
         process(
-            grandchild.clone(),
+            (*grandchild).clone(),
             &mut retrieval_criteria,
-            &mut filter_criteria,
-            &mut mandatory_codes,
+            if negated {
+                &mut negated_filter_criteria
+            } else {
+                &mut filter_criteria
+            },
+            &mut code_systems,
             &project,
         )?;
 
         // Only concatenate operator if it's not the last element
-        if index < ast.ast.children.len() - 1 {
+        if index < children.len() - 1 {
             retrieval_criteria += operator_str;
         }
     }
 
-    for code_system in mandatory_codes.iter() {
-        lists += format!(
-            "codesystem {}: '{}'\n",
-            code_system,
-            project.get_code_lists().get(code_system).unwrap_or(&(""))
-        )
-        .as_str();
+    if negated {
+        retrieval_criteria = format!("not ({})", retrieval_criteria);
+    }
+
+    for (alias, url) in code_systems.0.iter() {
+        lists += format!("codesystem {}: '{}'\n", alias, url).as_str();
     }
 
     cql = cql.replace("{{lists}}", lists.as_str());
@@ -93,11 +245,56 @@ fn generate_cql(ast: ast::Ast, project: Project) -> Result<String, FocusError> {
     Ok(cql)
 }
 
+/// Code systems the library declares by alias, gathered from all projects whose criteria are used
+#[derive(Default)]
+pub struct CodeSystems(IndexMap<String, &'static str>);
+
+impl CodeSystems {
+    /// Declares the project's mandatory code lists under their own aliases, as snippets may use them literally
+    fn declare_mandatory(&mut self, project: &Project) -> Result<(), FocusError> {
+        for alias in project.get_mandatory_code_lists() {
+            let url = project.get_code_lists().get(alias).copied().unwrap_or("");
+            match self.0.get(*alias) {
+                Some(declared) if *declared != url => {
+                    return Err(FocusError::AstCodeSystemConflict(format!(
+                        "{} stands for {} and {}",
+                        alias, declared, url
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    self.0.insert(alias.to_string(), url);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Declares a code list of the project for a placeholder, under a numbered alias if its own already stands for another code system
+    fn declare(&mut self, alias: &str, project: &Project) -> String {
+        let url = project.get_code_lists().get(alias).copied().unwrap_or("");
+        let mut declared_alias = alias.to_string();
+        for number in 2.. {
+            match self.0.get(&declared_alias) {
+                Some(declared) if *declared != url => {
+                    declared_alias = format!("{}_{}", alias, number);
+                }
+                Some(_) => break,
+                None => {
+                    self.0.insert(declared_alias.clone(), url);
+                    break;
+                }
+            }
+        }
+        declared_alias
+    }
+}
+
 pub fn process(
     child: ast::Child,
     retrieval_criteria: &mut String,
     filter_criteria: &mut String,
-    code_systems: &mut IndexSet<&str>,
+    code_systems: &mut CodeSystems,
     project: &Project,
 ) -> Result<(), FocusError> {
     let mut retrieval_cond: String = "(".to_string();
@@ -105,267 +302,212 @@ pub fn process(
 
     match child {
         ast::Child::Condition(condition) => {
+            // criteria of other projects come with their snippets and code systems
+            if let Some((namespace_project, key)) = projects::namespaced(&condition.key)? {
+                code_systems.declare_mandatory(&namespace_project)?;
+                return process(
+                    ast::Child::Condition(ast::Condition {
+                        key: key.to_string(),
+                        ..condition
+                    }),
+                    retrieval_criteria,
+                    filter_criteria,
+                    code_systems,
+                    &namespace_project,
+                );
+            }
+
             let condition_key_trans = condition.key.as_str();
 
-            let condition_snippet = project
-                .get_cql_snippets()
-                .get(&(condition_key_trans, CriterionRole::Query));
+            // one-sided comparisons may have snippets of their own instead of the rewritten range
+            let one_sided_role = match condition.type_ {
+                ast::ConditionType::LowerThan => Some(CriterionRole::LowerThan),
+                ast::ConditionType::GreaterThan => Some(CriterionRole::GreaterThan),
+                _ => None,
+            };
+            let condition_snippet = one_sided_role
+                .and_then(|role| project.get_cql_snippets().get(&(condition_key_trans, role)))
+                .or_else(|| {
+                    project
+                        .get_cql_snippets()
+                        .get(&(condition_key_trans, CriterionRole::Query))
+                })
+                .copied();
 
             let Some(snippet) = condition_snippet else {
                 return Err(FocusError::AstUnknownCriterion(
                     condition_key_trans.to_string(),
                 ));
             };
-            let mut condition_string = (*snippet).to_string();
-            let mut filter_string: String = String::new();
 
             let filter_snippet = project
                 .get_cql_snippets()
-                .get(&(condition_key_trans, CriterionRole::Filter));
-
-            let code_lists_option = project
-                .get_criterion_code_lists()
-                .get(&(condition_key_trans));
-            if let Some(code_lists_vec) = code_lists_option {
-                for (index, code_list) in code_lists_vec.iter().enumerate() {
-                    code_systems.insert(code_list);
-                    let placeholder = format!("{{{{A{}}}}}", (index + 1)); //to keep compatibility with snippets in typescript
-                    condition_string = condition_string.replace(placeholder.as_str(), code_list);
-                }
-            }
+                .get(&(condition_key_trans, CriterionRole::Filter))
+                .copied();
+
+            let count_string = match condition.count {
+                Some(count) => Some(format!(
+                    "Count({}) >= {}",
+                    list_of_values(
+                        &condition,
+                        CriterionRole::Retrieve,
+                        "counts",
+                        code_systems,
+                        project
+                    )?,
+                    count
+                )),
+                None => None,
+            };
 
-            if condition_string.contains("{{K}}") {
-                //observation loinc code, those only apply to query criteria, we don't filter specimens by observations
-                let observation_code_option = project
-                    .get_observation_loinc_codes()
-                    .get(&condition_key_trans);
-
-                if let Some(observation_code) = observation_code_option {
-                    condition_string = condition_string.replace("{{K}}", &escape(observation_code));
-                } else {
-                    return Err(FocusError::AstUnknownOption(
-                        condition_key_trans.to_string(),
-                    ));
-                }
+            let (condition_string, filter_string) =
+                fill_condition(condition, snippet, filter_snippet, code_systems, project)?;
+
+            // counted criteria only differ in the retrieval, specimens are filtered alike
+            retrieval_cond += count_string.unwrap_or(condition_string).as_str();
+
+            if !filter_cond.is_empty() && !filter_string.is_empty() {
+                filter_cond += " and ";
             }
 
-            if let Some(filtret) = filter_snippet {
-                filter_string = (*filtret).to_string();
+            filter_cond += filter_string.as_str(); // no condition needed, "" can be added with no change
+        }
+
+        ast::Child::Entity(entity) => {
+            let Some(source) = project.get_entity_sources().get(entity.entity.as_str()) else {
+                return Err(FocusError::AstUnknownCriterion(format!(
+                    "entity {}",
+                    entity.entity
+                )));
+            };
+            if entity.children.is_empty() {
+                return Err(FocusError::AstOperatorValueMismatch(format!(
+                    "Entity {} needs at least one condition",
+                    entity.entity
+                )));
             }
 
-            match condition.type_ {
-                ast::ConditionType::Between => {
-                    // both min and max values stated
-                    match condition.value {
-                        ast::ConditionValue::DateRange(date_range) => {
-                            let datetime_str_min = date_range.min.as_str();
-
-                            let datetime_min_maybe: Result<DateTime<Utc>, _> =
-                                datetime_str_min.parse();
-
-                            let datetime_min: DateTime<Utc> = if let Ok(datetime) =
-                                datetime_min_maybe
-                            {
-                                datetime
-                            } else {
-                                let naive_date_maybe =
-                                    NaiveDate::parse_from_str(datetime_str_min, "%Y-%m-%d"); //FIXME remove once Lens2 behaves, only return the error
-
-                                if let Ok(naive_date) = naive_date_maybe {
-                                    DateTime::<Utc>::from_naive_utc_and_offset(
-                                        naive_date.and_time(NaiveTime::default()),
-                                        Utc,
-                                    )
-                                } else {
-                                    return Err(FocusError::AstInvalidDateFormat(date_range.min));
-                                }
-                            };
-
-                            let date_str_min = format!("@{}", datetime_min.format("%Y-%m-%d"));
-
-                            condition_string =
-                                condition_string.replace("{{D1}}", date_str_min.as_str()); // no CQL injection possible here
-                            filter_string = filter_string.replace("{{D1}}", date_str_min.as_str()); // no CQL injection possible here
-                                                                                                    // no condition needed, "" stays ""
-
-                            let datetime_str_max = date_range.max.as_str();
-                            let datetime_max_maybe: Result<DateTime<Utc>, _> =
-                                datetime_str_max.parse();
-
-                            let datetime_max: DateTime<Utc> = if let Ok(datetime) =
-                                datetime_max_maybe
-                            {
-                                datetime
-                            } else {
-                                let naive_date_maybe =
-                                    NaiveDate::parse_from_str(datetime_str_max, "%Y-%m-%d"); //FIXME remove once Lens2 behaves, only return the error
-
-                                if let Ok(naive_date) = naive_date_maybe {
-                                    DateTime::<Utc>::from_naive_utc_and_offset(
-                                        naive_date.and_time(NaiveTime::default()),
-                                        Utc,
-                                    )
-                                } else {
-                                    return Err(FocusError::AstInvalidDateFormat(date_range.max));
-                                }
-                            };
-                            let date_str_max = format!("@{}", datetime_max.format("%Y-%m-%d"));
-
-                            condition_string =
-                                condition_string.replace("{{D2}}", date_str_max.as_str()); // no CQL injection possible here
-                            filter_string = filter_string.replace("{{D2}}", date_str_max.as_str());
-                            // no CQL injection possible here
-
-                            // no condition needed, "" stays ""
-                        }
-                        ast::ConditionValue::NumRange(num_range) => {
-                            condition_string = condition_string
-                                .replace("{{D1}}", num_range.min.to_string().as_str()); // no CQL injection possible here
-                            condition_string = condition_string
-                                .replace("{{D2}}", num_range.max.to_string().as_str()); // no CQL injection possible here
-                            filter_string =
-                                filter_string.replace("{{D1}}", num_range.min.to_string().as_str()); // no condition needed, "" stays ""; no CQL injection possible here
-                            filter_string =
-                                filter_string.replace("{{D2}}", num_range.max.to_string().as_str());
-                            // no CQL injection possible here
-
-                            // no condition needed, "" stays ""
-                        }
-                        other => {
-                            return Err(FocusError::AstOperatorValueMismatch(format!("Operator BETWEEN can only be used for numerical and date values, not for {:?}", other)));
-                        }
-                    }
-                } // deal with no lower or no upper value
-                ast::ConditionType::In => {
-                    // although in works in CQL, at least in some places, most of it is converted to multiple criteria with OR
-                    let operator_str = " or ";
-
-                    match condition.value {
-                        ast::ConditionValue::StringArray(string_array) => {
-                            let mut string_array_with_workarounds = string_array.clone();
-                            for value in string_array {
-                                if let Some(additional_values) =
-                                    project.get_sample_type_workarounds().get(value.as_str())
-                                {
-                                    for additional_value in additional_values {
-                                        string_array_with_workarounds
-                                            .push((*additional_value).into());
-                                    }
-                                }
-                            }
-                            let mut condition_humongous_string = "(".to_string();
-                            let mut filter_humongous_string = "(".to_string();
-
-                            for (index, string) in string_array_with_workarounds.iter().enumerate()
-                            {
-                                condition_humongous_string = condition_humongous_string
-                                    + "("
-                                    + condition_string.as_str()
-                                    + ")";
-                                condition_humongous_string =
-                                    condition_humongous_string.replace("{{C}}", &escape(string));
-
-                                filter_humongous_string =
-                                    filter_humongous_string + "(" + filter_string.as_str() + ")";
-                                filter_humongous_string =
-                                    filter_humongous_string.replace("{{C}}", &escape(string));
-
-                                // Only concatenate operator if it's not the last element
-                                if index < string_array_with_workarounds.len() - 1 {
-                                    condition_humongous_string += operator_str;
-                                    filter_humongous_string += operator_str;
-                                }
-                            }
-                            condition_string = condition_humongous_string + ")";
-
-                            if !filter_string.is_empty() {
-                                filter_string = filter_humongous_string + ")";
-                            }
-                        }
-                        other => {
-                            return Err(FocusError::AstOperatorValueMismatch(format!(
-                                "Operator IN can only be used for string arrays, not for {:?}",
-                                other
-                            )));
-                        }
-                    }
-                } // this becomes or of all
-                ast::ConditionType::Equals => match condition.value {
-                    ast::ConditionValue::String(string) => {
-                        let operator_str = " or ";
-                        let mut string_array_with_workarounds = vec![string.clone()];
-                        if let Some(additional_values) =
-                            project.get_sample_type_workarounds().get(string.as_str())
-                        {
-                            for additional_value in additional_values {
-                                string_array_with_workarounds.push((*additional_value).into());
-                            }
-                        }
-                        let mut condition_humongous_string = "(".to_string();
-                        let mut filter_humongous_string = "(".to_string();
-
-                        for (index, string) in string_array_with_workarounds.iter().enumerate() {
-                            condition_humongous_string =
-                                condition_humongous_string + "(" + condition_string.as_str() + ")";
-                            condition_humongous_string =
-                                condition_humongous_string.replace("{{C}}", &escape(string));
-
-                            filter_humongous_string =
-                                filter_humongous_string + "(" + filter_string.as_str() + ")";
-                            filter_humongous_string =
-                                filter_humongous_string.replace("{{C}}", &escape(string));
-
-                            // Only concatenate operator if it's not the last element
-                            if index < string_array_with_workarounds.len() - 1 {
-                                condition_humongous_string += operator_str;
-                                filter_humongous_string += operator_str;
-                            }
-                        }
-                        condition_string = condition_humongous_string + ")";
-
-                        if !filter_string.is_empty() {
-                            filter_string = filter_humongous_string + ")";
-                        }
-                    }
-                    other => {
-                        return Err(FocusError::AstOperatorValueMismatch(format!(
-                            "Operator EQUALS can only be used for string arrays, not for {:?}",
-                            other
-                        )));
-                    }
-                },
-                other => {
-                    // won't get it from Lens yet
-                    info!("Got this condition type which Lens is not programmed to send, ignoring: {:?}", other);
+            // one retrieve for all conditions, so that they have to hold for the same resource
+            let mut conditions = Vec::new();
+            let mut filters = Vec::new();
+            for condition in entity.children {
+                if condition.count.is_some() {
+                    return Err(FocusError::AstOperatorValueMismatch(format!(
+                        "Counts are not supported for conditions on entity {}",
+                        entity.entity
+                    )));
                 }
-            };
+                let Some(snippet) = project
+                    .get_entity_snippets()
+                    .get(&(entity.entity.as_str(), condition.key.as_str()))
+                    .copied()
+                else {
+                    return Err(FocusError::AstUnknownCriterion(format!(
+                        "{} (for entity {})",
+                        condition.key, entity.entity
+                    )));
+                };
+                let filter_snippet = project
+                    .get_cql_snippets()
+                    .get(&(condition.key.as_str(), CriterionRole::Filter))
+                    .copied();
+
+                let (condition_string, filter_string) =
+                    fill_condition(condition, snippet, filter_snippet, code_systems, project)?;
+                conditions.push(condition_string);
+                if !filter_string.is_empty() {
+                    filters.push(filter_string);
+                }
+            }
 
-            retrieval_cond += condition_string.as_str();
+            retrieval_cond +=
+                format!("exists from {}\nwhere {}", source, conditions.join(" and ")).as_str();
+            filter_cond += filters.join(" and ").as_str();
+        }
 
-            if !filter_cond.is_empty() && !filter_string.is_empty() {
-                filter_cond += " and ";
+        ast::Child::Temporal(temporal) => {
+            let timing = match (temporal.relation, temporal.days) {
+                (ast::TemporalRelation::Before, None) => "before".to_string(),
+                (ast::TemporalRelation::After, None) => "after".to_string(),
+                (ast::TemporalRelation::Before, Some(days)) => {
+                    format!("{} days or less before", days)
+                }
+                (ast::TemporalRelation::After, Some(days)) => {
+                    format!("{} days or less after", days)
+                }
+                (ast::TemporalRelation::Within, Some(days)) => format!("within {} days of", days),
+                (ast::TemporalRelation::Within, None) => {
+                    return Err(FocusError::AstOperatorValueMismatch(
+                        "Temporal relation WITHIN needs a number of days".into(),
+                    ));
+                }
+            };
+            if temporal.anchor.count.is_some() || temporal.event.count.is_some() {
+                return Err(FocusError::AstOperatorValueMismatch(
+                    "Counts are not supported in temporal relations".into(),
+                ));
             }
-
-            filter_cond += filter_string.as_str(); // no condition needed, "" can be added with no change
+            let anchor_dates = list_of_values(
+                &temporal.anchor,
+                CriterionRole::Event,
+                "temporal relations",
+                code_systems,
+                project,
+            )?;
+            let event_dates = list_of_values(
+                &temporal.event,
+                CriterionRole::Event,
+                "temporal relations",
+                code_systems,
+                project,
+            )?;
+
+            // no specimen filter, the relation is about the patient's history
+            retrieval_cond += format!(
+                "exists (\nfrom {} AnchorDate, {} EventDate\nwhere EventDate {} AnchorDate)",
+                anchor_dates, event_dates, timing
+            )
+            .as_str();
         }
 
         ast::Child::Operation(operation) => {
             let operator_str = match operation.operand {
-                ast::Operand::And => " and ",
+                ast::Operand::And | ast::Operand::Not => " and ",
                 ast::Operand::Or => " or ",
             };
 
-            for (index, grandchild) in operation.children.iter().enumerate() {
+            let children: Vec<&ast::Child> = operation
+                .children
+                .iter()
+                .filter(|child| !child.is_empty_group())
+                .collect();
+            let negated = operation.operand == ast::Operand::Not;
+            if negated {
+                if children.is_empty() {
+                    return Err(FocusError::AstOperatorValueMismatch(
+                        "Operator NOT needs at least one criterion".into(),
+                    ));
+                }
+                retrieval_cond += "not (";
+            }
+            // a negated specimen filter would also drop specimens of patients the criterion does not apply to, so specimens are not filtered by negated criteria
+            let mut negated_filter_cond = String::new();
+
+            for (index, grandchild) in children.iter().enumerate() {
                 process(
-                    grandchild.clone(),
+                    (*grandchild).clone(),
                     &mut retrieval_cond,
-                    &mut filter_cond,
+                    if negated {
+                        &mut negated_filter_cond
+                    } else {
+                        &mut filter_cond
+                    },
                     code_systems,
                     project,
                 )?;
 
                 // Only concatenate operator if it's not the last element
-                if index < operation.children.len() - 1 {
+                if index < children.len() - 1 {
                     retrieval_cond += operator_str;
                     if !filter_cond.is_empty()
                         && !filter_cond.ends_with(" or ")
@@ -375,6 +517,9 @@ pub fn process(
                     }
                 }
             }
+            if negated {
+                retrieval_cond += ")";
+            }
             if let Some(pos) = filter_cond.rfind(')') {
                 _ = filter_cond.split_off(pos + 1);
             }
@@ -396,6 +541,404 @@ pub fn process(
     Ok(())
 }
 
+/// Fills the values of the condition into its snippet and the filter snippet, if any
+fn fill_condition(
+    condition: ast::Condition,
+    snippet: &str,
+    filter_snippet: Option<&str>,
+    code_systems: &mut CodeSystems,
+    project: &Project,
+) -> Result<(String, String), FocusError> {
+    let mut condition_string = fill_code_lists(snippet, &condition.key, code_systems, project);
+    let mut filter_string: String = String::new();
+
+    if condition_string.contains("{{K}}") {
+        //observation loinc code, those only apply to query criteria, we don't filter specimens by observations
+        let observation_code_option = project
+            .get_observation_loinc_codes()
+            .get(condition.key.as_str());
+
+        if let Some(observation_code) = observation_code_option {
+            condition_string = condition_string.replace("{{K}}", &escape(observation_code));
+        } else {
+            return Err(FocusError::AstUnknownOption(condition.key.clone()));
+        }
+    }
+
+    if let Some(filtret) = filter_snippet {
+        filter_string = filtret.to_string();
+    }
+
+    // numbers of criteria measured in a unit become quantities in that unit
+    let expected_unit = project
+        .get_criterion_units()
+        .get(condition.key.as_str())
+        .copied();
+    let numeric = |value: f64| match (expected_unit, &condition.unit) {
+        (Some(expected), unit) => Ok(units::cql_quantity(
+            units::convert(value, unit.as_deref().unwrap_or(expected), expected)?,
+            expected,
+        )),
+        (None, Some(unit)) => Err(FocusError::AstInvalidUnit(format!(
+            "{} has no unit, so {} can't be used",
+            condition.key, unit
+        ))),
+        (None, None) => Ok(value.to_string()),
+    };
+
+    match condition.type_ {
+        ast::ConditionType::Between => {
+            let (min, max) = match condition.value {
+                // a range from 2020 to 2021 covers both years completely
+                ast::ConditionValue::DateRange(date_range) => (
+                    date_range
+                        .min
+                        .map(|min| cql_date(min.first_day()))
+                        .unwrap_or_else(|| OPEN_DATE_MIN.to_string()),
+                    date_range
+                        .max
+                        .map(|max| cql_date(max.last_day()))
+                        .unwrap_or_else(|| OPEN_DATE_MAX.to_string()),
+                ),
+                ast::ConditionValue::NumRange(num_range) => {
+                    (numeric(num_range.min)?, numeric(num_range.max)?)
+                }
+                other => {
+                    return Err(FocusError::AstOperatorValueMismatch(format!("Operator BETWEEN can only be used for numerical and date values, not for {:?}", other)));
+                }
+            };
+            // no CQL injection possible here; no condition needed for the filter, "" stays ""
+            condition_string = condition_string
+                .replace("{{D1}}", min.as_str())
+                .replace("{{D2}}", max.as_str());
+            filter_string = filter_string
+                .replace("{{D1}}", min.as_str())
+                .replace("{{D2}}", max.as_str());
+        }
+        ast::ConditionType::LowerThan | ast::ConditionType::GreaterThan => {
+            // strict one-sided comparisons, the BETWEEN of the snippet is rewritten to < or >
+            let lower_than = matches!(condition.type_, ast::ConditionType::LowerThan);
+            let bound = match condition.value {
+                ast::ConditionValue::Number(number) => numeric(number)?,
+                ast::ConditionValue::String(date) => {
+                    let date: ast::AstDate = date.parse()?;
+                    // lower than 2020 is before 2020 begins, greater than 2020 after it ends
+                    cql_date(if lower_than {
+                        date.first_day()
+                    } else {
+                        date.last_day()
+                    })
+                }
+                other => {
+                    return Err(FocusError::AstOperatorValueMismatch(format!(
+                        "Operator {:?} can only be used for numerical and date values, not for {:?}",
+                        condition.type_, other
+                    )));
+                }
+            };
+            let compare = |snippet: &str| {
+                if snippet.contains("{{D}}") {
+                    return Ok(snippet.to_string());
+                }
+                one_sided(snippet, lower_than).ok_or_else(|| {
+                    FocusError::AstOperatorValueMismatch(format!(
+                        "Operator {:?} can't be used for {}, its snippet has no range of plain bounds and no {} snippet",
+                        condition.type_,
+                        condition.key,
+                        if lower_than { "lowerthan" } else { "greaterthan" }
+                    ))
+                })
+            };
+            // no CQL injection possible here; no condition needed for the filter, "" stays ""
+            condition_string = compare(&condition_string)?.replace("{{D}}", bound.as_str());
+            if !filter_string.is_empty() {
+                filter_string = compare(&filter_string)?.replace("{{D}}", bound.as_str());
+            }
+        }
+        ast::ConditionType::In => match condition.value {
+            // although in works in CQL, at least in some places, most of it is converted to multiple criteria with OR
+            ast::ConditionValue::StringArray(string_array) => {
+                (condition_string, filter_string) = join_values(
+                    &condition.key,
+                    &string_array,
+                    &condition_string,
+                    &filter_string,
+                    " or ",
+                    project,
+                );
+            }
+            other => {
+                return Err(FocusError::AstOperatorValueMismatch(format!(
+                    "Operator IN can only be used for string arrays, not for {:?}",
+                    other
+                )));
+            }
+        }, // this becomes or of all
+        ast::ConditionType::Equals => match condition.value {
+            ast::ConditionValue::String(string) => {
+                (condition_string, filter_string) = join_values(
+                    &condition.key,
+                    &[string],
+                    &condition_string,
+                    &filter_string,
+                    " or ",
+                    project,
+                );
+            }
+            other => {
+                return Err(FocusError::AstOperatorValueMismatch(format!(
+                    "Operator EQUALS can only be used for strings, not for {:?}",
+                    other
+                )));
+            }
+        },
+        ast::ConditionType::NotEquals => match condition.value {
+            ast::ConditionValue::String(string) => {
+                let (equals_string, _) = join_values(
+                    &condition.key,
+                    &[string],
+                    &condition_string,
+                    &filter_string,
+                    " or ",
+                    project,
+                );
+                condition_string = format!("not {}", equals_string);
+                // like with NOT, specimens are not filtered by negated criteria
+                filter_string = String::new();
+            }
+            other => {
+                return Err(FocusError::AstOperatorValueMismatch(format!(
+                    "Operator NOT_EQUALS can only be used for strings, not for {:?}",
+                    other
+                )));
+            }
+        },
+        ast::ConditionType::Contains => match condition.value {
+            ast::ConditionValue::StringArray(string_array) => {
+                // every value has to be present
+                let joined: Vec<(String, String)> = string_array
+                    .into_iter()
+                    .map(|string| {
+                        join_values(
+                            &condition.key,
+                            &[string],
+                            &condition_string,
+                            &filter_string,
+                            " or ",
+                            project,
+                        )
+                    })
+                    .collect();
+                condition_string = format!(
+                    "({})",
+                    joined
+                        .iter()
+                        .map(|(condition, _)| condition.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" and ")
+                );
+                if !filter_string.is_empty() {
+                    filter_string = format!(
+                        "({})",
+                        joined
+                            .iter()
+                            .map(|(_, filter)| filter.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" and ")
+                    );
+                }
+            }
+            other => {
+                return Err(FocusError::AstOperatorValueMismatch(format!(
+                    "Operator CONTAINS can only be used for string arrays, use EQUALS for {:?}",
+                    other
+                )));
+            }
+        },
+    };
+
+    Ok((condition_string, filter_string))
+}
+
+/// Replaces the code list placeholders of the criterion's snippet and registers the code lists as needed
+fn fill_code_lists(
+    snippet: &str,
+    key: &str,
+    code_systems: &mut CodeSystems,
+    project: &Project,
+) -> String {
+    let mut filled = snippet.to_string();
+    if let Some(code_lists_vec) = project.get_criterion_code_lists().get(key) {
+        for (index, code_list) in code_lists_vec.iter().enumerate() {
+            let alias = code_systems.declare(code_list, project);
+            let placeholder = format!("{{{{A{}}}}}", (index + 1)); //to keep compatibility with snippets in typescript
+            filled = filled.replace(placeholder.as_str(), &alias);
+        }
+    }
+    filled
+}
+
+/// Generates the union of the lists the criterion's snippet for the role gives for each value, e.g. the dates of events
+fn list_of_values(
+    condition: &ast::Condition,
+    role: CriterionRole,
+    usage: &str,
+    code_systems: &mut CodeSystems,
+    project: &Project,
+) -> Result<String, FocusError> {
+    let Some(snippet) = project
+        .get_cql_snippets()
+        .get(&(condition.key.as_str(), role))
+        .copied()
+    else {
+        return Err(FocusError::AstUnknownCriterion(format!(
+            "{} (for {})",
+            condition.key, usage
+        )));
+    };
+    let snippet = fill_code_lists(snippet, &condition.key, code_systems, project);
+
+    let values = match (&condition.type_, &condition.value) {
+        (ast::ConditionType::Equals, ast::ConditionValue::String(string)) => vec![string.clone()],
+        (ast::ConditionType::In, ast::ConditionValue::StringArray(string_array)) => {
+            string_array.clone()
+        }
+        (type_, value) => {
+            return Err(FocusError::AstOperatorValueMismatch(format!(
+                "Only EQUALS with a string and IN with a string array are supported for {}, not {:?} with {:?}",
+                usage, type_, value
+            )));
+        }
+    };
+
+    Ok(join_values(&condition.key, &values, &snippet, "", " union ", project).0)
+}
+
+/// Rewrites the ranges `x between min({{D1}}) and max({{D2}})` of a snippet to `x < max({{D}})` or `x > min({{D}})`,
+/// keeping functions like `Ceiling` around the bound; None if the snippet has no such range or its bounds are more
+/// than the placeholder in function calls, e.g. quantities like `{{D1}} 'kg'`
+fn one_sided(snippet: &str, lower_than: bool) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = snippet;
+    while let Some(between) = rest.find(" between ") {
+        let min_start = between + " between ".len();
+        let and = min_start + rest[min_start..].find(" and ")?;
+        let min = &rest[min_start..and];
+        let max_start = and + " and ".len();
+        let placeholder = max_start + rest[max_start..].find("{{D2}}")?;
+        if !min.contains("{{D1}}") {
+            return None;
+        }
+        // the max bound ends where the parentheses opened before its placeholder are closed
+        let open = rest[max_start..placeholder]
+            .matches('(')
+            .count()
+            .saturating_sub(rest[max_start..placeholder].matches(')').count());
+        let max_end = placeholder + "{{D2}}".len() + open;
+        let max = rest.get(max_start..max_end)?;
+        if !plain_bound(min, "{{D1}}")
+            || !plain_bound(max, "{{D2}}")
+            || !ends_expression(&rest[max_end..])
+        {
+            return None;
+        }
+
+        result += &rest[..between];
+        if lower_than {
+            result += &format!(" < {}", max.replace("{{D2}}", "{{D}}"));
+        } else {
+            result += &format!(" > {}", min.replace("{{D1}}", "{{D}}"));
+        }
+        rest = &rest[max_end..];
+    }
+    if result.is_empty() {
+        return None;
+    }
+    result += rest;
+    Some(result)
+}
+
+/// Whether the bound is just the placeholder, possibly in function calls like `Ceiling({{D1}})`
+fn plain_bound(bound: &str, placeholder: &str) -> bool {
+    bound
+        .split_once(placeholder)
+        .is_some_and(|(before, after)| {
+            before
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '.' || c == '(')
+                && after.chars().all(|c| c == ')')
+        })
+}
+
+/// Whether the text after a range starts with something ending the range's expression, so nothing belongs to its bound
+fn ends_expression(text: &str) -> bool {
+    let text = text.trim_start_matches(' ');
+    text.is_empty()
+        || text.starts_with([')', '\n'])
+        || ["and", "or", "xor", "implies", "then", "else", "return"]
+            .iter()
+            .any(|keyword| {
+                text.strip_prefix(keyword)
+                    .is_some_and(|after| after.starts_with([' ', '(', '\n']))
+            })
+}
+
+/// Stands in for the missing end of an open-ended date range
+const OPEN_DATE_MIN: &str = "@0001-01-01";
+const OPEN_DATE_MAX: &str = "@9999-12-31";
+
+/// Converts a date to a CQL date literal, no CQL injection possible in the result
+fn cql_date(date: NaiveDate) -> String {
+    format!("@{}", date.format("%Y-%m-%d"))
+}
+
+/// Fills the condition and filter snippets in once per value and per value the criterion maps it to, joined by the operator
+fn join_values(
+    key: &str,
+    values: &[String],
+    condition_string: &str,
+    filter_string: &str,
+    operator_str: &str,
+    project: &Project,
+) -> (String, String) {
+    let mut mapped_values = values.to_vec();
+    if let Some(mappings) = project.get_value_mappings().get(key) {
+        for value in values {
+            if let Some(additional_values) = mappings.get(value.as_str()) {
+                for additional_value in additional_values {
+                    mapped_values.push((*additional_value).into());
+                }
+            }
+        }
+    }
+
+    let mut condition_humongous_string = "(".to_string();
+    let mut filter_humongous_string = "(".to_string();
+
+    for (index, value) in mapped_values.iter().enumerate() {
+        condition_humongous_string = condition_humongous_string + "(" + condition_string + ")";
+        condition_humongous_string = condition_humongous_string.replace("{{C}}", &escape(value));
+
+        filter_humongous_string = filter_humongous_string + "(" + filter_string + ")";
+        filter_humongous_string = filter_humongous_string.replace("{{C}}", &escape(value));
+
+        // Only concatenate operator if it's not the last element
+        if index < mapped_values.len() - 1 {
+            condition_humongous_string += operator_str;
+            filter_humongous_string += operator_str;
+        }
+    }
+
+    if filter_string.is_empty() {
+        (condition_humongous_string + ")", String::new())
+    } else {
+        (
+            condition_humongous_string + ")",
+            filter_humongous_string + ")",
+        )
+    }
+}
+
 fn escape(value: &str) -> String {
     value
         .replace("\\", "\\\\")
@@ -573,6 +1116,441 @@ mod test {
         );
     }
 
+    const C50_NOT_MALE: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"AND","children":[{"operand":"OR","children":[{"key":"diagnosis","type":"EQUALS","system":"http://fhir.de/CodeSystem/dimdi/icd-10-gm","value":"C50"}]},{"operand":"NOT","children":[{"key":"gender","type":"EQUALS","system":"","value":"male"}]}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const NOT_FFPE: &str = r#"{"ast":{"operand":"NOT","children":[{"key":"sample_kind","type":"EQUALS","system":"","value":"tissue-ffpe"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const EMPTY_NOT: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"NOT","children":[]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const NOT_EMPTY_GROUP: &str = r#"{"ast":{"operand":"NOT","children":[{"operand":"AND","children":[]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const NESTED_NOT_EMPTY_GROUP: &str = r#"{"ast":{"operand":"AND","children":[{"key":"gender","type":"EQUALS","system":"","value":"male"},{"operand":"NOT","children":[{"operand":"AND","children":[{"operand":"OR","children":[]}]}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const NOT_WITH_EMPTY_GROUP: &str = r#"{"ast":{"operand":"NOT","children":[{"operand":"AND","children":[]},{"key":"gender","type":"EQUALS","system":"","value":"male"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_not() {
+        let generated_cql =
+            generate_cql(serde_json::from_str(C50_NOT_MALE).unwrap(), Project::Bbmri).unwrap();
+        assert!(generated_cql.contains("exists[Condition: Code 'C50' from icd10gm]"));
+        assert!(generated_cql.contains(" and (not ((((Patient.gender = 'male')))))"));
+
+        // negated specimen criteria exclude patients, but don't filter specimens
+        let generated_cql =
+            generate_cql(serde_json::from_str(NOT_FFPE).unwrap(), Project::Bbmri).unwrap();
+        assert!(generated_cql.contains("define InInitialPopulation:\n(not (((( exists [Specimen: Code 'tissue-ffpe' from SampleMaterialType])"));
+        assert!(generated_cql.contains("if InInitialPopulation then [Specimen] S  else"));
+
+        assert!(generate_cql(serde_json::from_str(EMPTY_NOT).unwrap(), Project::Bbmri).is_err());
+
+        // NOT over groups without criteria is an error, not empty parentheses
+        for ast in [NOT_EMPTY_GROUP, NESTED_NOT_EMPTY_GROUP] {
+            let ast: ast::Ast = serde_json::from_str(ast).unwrap();
+            assert!(generate_cql(ast.clone(), Project::Bbmri).is_err());
+            assert!(generate_body(ast, Project::Bbmri, None).is_err());
+        }
+
+        // empty groups next to criteria are left out
+        let generated_cql = generate_cql(
+            serde_json::from_str(NOT_WITH_EMPTY_GROUP).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(!generated_cql.contains("(()"));
+        assert!(generated_cql.contains("(not ((((Patient.gender = 'male')))))"));
+    }
+
+    const NOT_EQUALS_MALE: &str = r#"{"ast":{"operand":"AND","children":[{"key":"gender","type":"NOT_EQUALS","system":"","value":"male"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const DONOR_AGE_LOWER_THAN_70: &str = r#"{"ast":{"operand":"AND","children":[{"key":"donor_age","type":"LOWER_THAN","system":"","value":70}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const SAMPLING_DATE_GREATER_THAN_2020: &str = r#"{"ast":{"operand":"AND","children":[{"key":"sampling_date","type":"GREATER_THAN","system":"","value":"2020-01-01"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const CONTAINS_MALE: &str = r#"{"ast":{"operand":"AND","children":[{"key":"gender","type":"CONTAINS","system":"","value":"male"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const CONTAINS_MALE_AND_FEMALE: &str = r#"{"ast":{"operand":"AND","children":[{"key":"gender","type":"CONTAINS","system":"","value":["male","female"]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const LOWER_THAN_ARRAY: &str = r#"{"ast":{"operand":"AND","children":[{"key":"donor_age","type":"LOWER_THAN","system":"","value":["70"]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_condition_types() {
+        let generated_cql = generate_cql(
+            serde_json::from_str(NOT_EQUALS_MALE).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql.contains("((not ((Patient.gender = 'male'))))"));
+
+        let generated_cql = generate_cql(
+            serde_json::from_str(DONOR_AGE_LOWER_THAN_70).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql.contains("AgeInYears() < Ceiling(70)"));
+
+        let generated_cql = generate_cql(
+            serde_json::from_str(SAMPLING_DATE_GREATER_THAN_2020).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql
+            .contains("where FHIRHelpers.ToDateTime(S.collection.collected) > @2020-01-01"));
+        assert!(generated_cql.contains(
+            "[Specimen] S where (((FHIRHelpers.ToDateTime(S.collection.collected) > @2020-01-01) ))"
+        ));
+
+        let generated_cql = generate_cql(
+            serde_json::from_str(CONTAINS_MALE_AND_FEMALE).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql
+            .contains("(((Patient.gender = 'male')) and ((Patient.gender = 'female')))"));
+
+        // a single string would mean the same as EQUALS
+        assert!(matches!(
+            generate_cql(serde_json::from_str(CONTAINS_MALE).unwrap(), Project::Bbmri),
+            Err(FocusError::AstOperatorValueMismatch(_))
+        ));
+
+        assert!(matches!(
+            generate_cql(
+                serde_json::from_str(LOWER_THAN_ARRAY).unwrap(),
+                Project::Bbmri
+            ),
+            Err(FocusError::AstOperatorValueMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_one_sided() {
+        pretty_assertions::assert_eq!(
+            one_sided(
+                "AgeInYears() between Ceiling({{D1}}) and Ceiling({{D2}})",
+                true
+            ),
+            Some("AgeInYears() < Ceiling({{D}})".to_string())
+        );
+        pretty_assertions::assert_eq!(
+            one_sided(
+                "AgeInYears() between Ceiling({{D1}}) and Ceiling({{D2}})",
+                false
+            ),
+            Some("AgeInYears() > Ceiling({{D}})".to_string())
+        );
+        pretty_assertions::assert_eq!(
+            one_sided(
+                "(O.value as Quantity) between {{D1}} and {{D2}} and (X between {{D1}} and {{D2}})",
+                true
+            ),
+            Some("(O.value as Quantity) < {{D}} and (X < {{D}})".to_string())
+        );
+        pretty_assertions::assert_eq!(one_sided("year from C.onset >= {{D1}}", true), None);
+        // the unit would remain after the bound
+        pretty_assertions::assert_eq!(
+            one_sided(
+                "(O.value as Quantity) between {{D1}} 'kg' and {{D2}} 'kg'",
+                false
+            ),
+            None
+        );
+        pretty_assertions::assert_eq!(
+            one_sided("(O.value as Quantity) between {{D1}} and {{D2}} 'kg'", true),
+            None
+        );
+    }
+
+    const GRIP_STRENGTH_GREATER_THAN_30: &str = r#"{"ast":{"operand":"AND","children":[{"key":"grip_strength","type":"GREATER_THAN","value":30}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_one_sided_snippets() {
+        let project = |cql_snippets| {
+            Project::Custom(Box::leak(Box::new(crate::projects::CustomProject {
+                cql_snippets,
+                cql_template: "{{retrieval_criteria}}",
+                ..Default::default()
+            })))
+        };
+        let range = (
+            ("grip_strength", CriterionRole::Query),
+            "exists from [Observation] O\nwhere (O.value as Quantity) between {{D1}} 'kg' and {{D2}} 'kg'",
+        );
+
+        assert!(generate_cql(
+            serde_json::from_str(GRIP_STRENGTH_GREATER_THAN_30).unwrap(),
+            project(std::collections::HashMap::from([range]))
+        )
+        .is_err());
+
+        let generated_cql = generate_cql(
+            serde_json::from_str(GRIP_STRENGTH_GREATER_THAN_30).unwrap(),
+            project(std::collections::HashMap::from([
+                range,
+                (
+                    ("grip_strength", CriterionRole::GreaterThan),
+                    "exists from [Observation] O\nwhere (O.value as Quantity) > {{D}} 'kg'",
+                ),
+            ])),
+        )
+        .unwrap();
+        assert!(generated_cql.contains("where (O.value as Quantity) > 30 'kg'"));
+    }
+
+    const SAMPLING_DATE_2020_TO_JUNE_2021: &str = r#"{"ast":{"operand":"AND","children":[{"key":"sampling_date","type":"BETWEEN","system":"","value":{"min":"2020","max":"2021-06"}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const SAMPLING_DATE_UNTIL_2020: &str = r#"{"ast":{"operand":"AND","children":[{"key":"sampling_date","type":"BETWEEN","system":"","value":{"min":"","max":"2020"}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const SAMPLING_DATE_LOWER_THAN_FEBRUARY_2020: &str = r#"{"ast":{"operand":"AND","children":[{"key":"sampling_date","type":"LOWER_THAN","system":"","value":"2020-02"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_date_precision() {
+        let generated_cql = generate_cql(
+            serde_json::from_str(SAMPLING_DATE_2020_TO_JUNE_2021).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql.contains("between @2020-01-01 and @2021-06-30"));
+
+        let generated_cql = generate_cql(
+            serde_json::from_str(SAMPLING_DATE_UNTIL_2020).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql.contains("between @0001-01-01 and @2020-12-31"));
+
+        let generated_cql = generate_cql(
+            serde_json::from_str(SAMPLING_DATE_LOWER_THAN_FEBRUARY_2020).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(
+            generated_cql.contains("FHIRHelpers.ToDateTime(S.collection.collected) < @2020-02-01")
+        );
+    }
+
+    const RADIOTHERAPY_AFTER_LUNG_CANCER: &str = r#"{"ast":{"operand":"AND","children":[{"relation":"AFTER","days":180,"anchor":{"key":"diagnosis","type":"IN","value":["C34.0","C34.1"]},"event":{"key":"procedure","type":"EQUALS","value":"ST"}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const SURGERY_BEFORE_DIAGNOSIS: &str = r#"{"ast":{"operand":"AND","children":[{"key":"gender","type":"EQUALS","value":"female"},{"relation":"BEFORE","anchor":{"key":"diagnosis","type":"EQUALS","value":"C50"},"event":{"key":"procedure","type":"EQUALS","value":"OP"}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const WITHIN_WITHOUT_DAYS: &str = r#"{"ast":{"operand":"AND","children":[{"relation":"WITHIN","anchor":{"key":"diagnosis","type":"EQUALS","value":"C50"},"event":{"key":"procedure","type":"EQUALS","value":"OP"}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const GENDER_AFTER_DIAGNOSIS: &str = r#"{"ast":{"operand":"AND","children":[{"relation":"AFTER","anchor":{"key":"diagnosis","type":"EQUALS","value":"C50"},"event":{"key":"gender","type":"EQUALS","value":"female"}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_temporal() {
+        let generated_cql = generate_cql(
+            serde_json::from_str(RADIOTHERAPY_AFTER_LUNG_CANCER).unwrap(),
+            Project::Dktk,
+        )
+        .unwrap();
+        pretty_assertions::assert_eq!(
+            generated_cql,
+            include_str!("../resources/test/result_radiotherapy_after_lung_cancer.cql").to_string()
+        );
+
+        let generated_cql = generate_cql(
+            serde_json::from_str(SURGERY_BEFORE_DIAGNOSIS).unwrap(),
+            Project::Dktk,
+        )
+        .unwrap();
+        assert!(generated_cql.contains("(Patient.gender = 'female'))) and (exists (\nfrom (([Condition: Code 'C50' from icd10] C\nreturn FHIRHelpers.ToDateTime(C.onset))) AnchorDate, "));
+        assert!(generated_cql.contains("\nwhere EventDate before AnchorDate))"));
+
+        assert!(generate_cql(
+            serde_json::from_str(WITHIN_WITHOUT_DAYS).unwrap(),
+            Project::Dktk
+        )
+        .is_err());
+        // criteria without an event snippet can't be related in time
+        assert!(generate_cql(
+            serde_json::from_str(GENDER_AFTER_DIAGNOSIS).unwrap(),
+            Project::Dktk
+        )
+        .is_err());
+    }
+
+    const SAME_SPECIMEN_FFPE_AT_MINUS_80: &str = r#"{"ast":{"operand":"AND","children":[{"entity":"specimen","children":[{"key":"sample_kind","type":"EQUALS","value":"tissue-ffpe"},{"key":"storage_temperature","type":"EQUALS","value":"temperature-80"}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const SAME_SPECIMEN_GENDER: &str = r#"{"ast":{"operand":"AND","children":[{"entity":"specimen","children":[{"key":"gender","type":"EQUALS","value":"male"}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const SAME_DONOR: &str = r#"{"ast":{"operand":"AND","children":[{"entity":"donor","children":[{"key":"gender","type":"EQUALS","value":"male"}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_entity() {
+        let generated_cql = generate_cql(
+            serde_json::from_str(SAME_SPECIMEN_FFPE_AT_MINUS_80).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        pretty_assertions::assert_eq!(
+            generated_cql,
+            include_str!("../resources/test/result_same_specimen_ffpe_at_minus_80.cql").to_string()
+        );
+
+        assert!(generate_cql(
+            serde_json::from_str(SAME_SPECIMEN_GENDER).unwrap(),
+            Project::Bbmri
+        )
+        .is_err());
+        assert!(generate_cql(serde_json::from_str(SAME_DONOR).unwrap(), Project::Bbmri).is_err());
+    }
+
+    const AT_LEAST_3_SERUM_SAMPLES: &str = r#"{"ast":{"operand":"AND","children":[{"key":"sample_kind","type":"EQUALS","value":"blood-serum","count":3}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const AT_LEAST_2_GENDERS: &str = r#"{"ast":{"operand":"AND","children":[{"key":"gender","type":"EQUALS","value":"male","count":2}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_count() {
+        let generated_cql = generate_cql(
+            serde_json::from_str(AT_LEAST_3_SERUM_SAMPLES).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql.contains("define InInitialPopulation:\n((Count((([Specimen: Code 'blood-serum' from SampleMaterialType]) union ([Specimen: Code 'serum' from SampleMaterialType]))) >= 3))"));
+        // specimens are filtered like without the count
+        assert!(generated_cql.contains("if InInitialPopulation then [Specimen] S where (((( (S.type.coding.code contains 'blood-serum')) or ( (S.type.coding.code contains 'serum'))))) else"));
+
+        assert!(generate_cql(
+            serde_json::from_str(AT_LEAST_2_GENDERS).unwrap(),
+            Project::Bbmri
+        )
+        .is_err());
+    }
+
+    const WEIGHT_IN_POUNDS: &str = r#"{"ast":{"operand":"OR","children":[{"key":"body_weight","type":"BETWEEN","value":{"min":110,"max":220},"unit":"[lb_av]"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const AGE_IN_KG: &str = r#"{"ast":{"operand":"OR","children":[{"key":"donor_age","type":"BETWEEN","value":{"min":20,"max":40},"unit":"kg"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_units() {
+        let generated_cql = generate_cql(
+            serde_json::from_str(WEIGHT_IN_POUNDS).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql
+            .contains("where (O.value as Quantity) between 49.895161 'kg' and 99.790321 'kg'"));
+
+        assert!(generate_cql(serde_json::from_str(AGE_IN_KG).unwrap(), Project::Bbmri).is_err());
+    }
+
+    const NORMAL_BMI_AND_WEIGHT: &str = r#"{"ast":{"operand":"AND","children":[{"key":"bmi","type":"BETWEEN","value":{"min":18.5,"max":25}},{"key":"body_weight","type":"BETWEEN","value":{"min":50,"max":90}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_bmi_and_body_weight_ranges() {
+        // these snippets used to require values below the minimum and above the maximum at once, which never matched
+        let generated_cql = generate_cql(
+            serde_json::from_str(NORMAL_BMI_AND_WEIGHT).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql
+            .contains("where (O.value as Quantity) between 18.5 'kg/m2' and 25 'kg/m2'"));
+        assert!(generated_cql.contains("where (O.value as Quantity) between 50 'kg' and 90 'kg'"));
+        assert!(!generated_cql.contains("(O.value as Quantity) <"));
+    }
+
+    const FEMALE_WITH_DKTK_LUNG_CANCER: &str = r#"{"ast":{"operand":"AND","children":[{"key":"gender","type":"EQUALS","value":"female"},{"key":"dktk:diagnosis","type":"EQUALS","value":"C34"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const UNKNOWN_NAMESPACE: &str = r#"{"ast":{"operand":"AND","children":[{"key":"nonexistent:diagnosis","type":"EQUALS","value":"C34"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_namespaces() {
+        let generated_cql = generate_cql(
+            serde_json::from_str(FEMALE_WITH_DKTK_LUNG_CANCER).unwrap(),
+            Project::Bbmri,
+        )
+        .unwrap();
+        assert!(generated_cql.contains("codesystem icd10: 'http://hl7.org/fhir/sid/icd-10'"));
+        assert!(generated_cql
+            .contains("codesystem icd10_2: 'http://fhir.de/CodeSystem/bfarm/icd-10-gm'"));
+        assert!(generated_cql.contains("codesystem loinc: 'http://loinc.org'"));
+        assert!(generated_cql.contains("Code 'C34' from icd10_2"));
+        assert!(!generated_cql.contains("Code 'C34' from icd10)"));
+
+        assert!(generate_cql(
+            serde_json::from_str(UNKNOWN_NAMESPACE).unwrap(),
+            Project::Bbmri
+        )
+        .is_err());
+    }
+
+    const STRATIFIER_BODY: &str = r#"{"measure":{"group":[{"code":{"text":"patient"},"population":[{"criteria":{"expression":"InInitialPopulation"}}],"stratifier":[{"code":{"text":"age"},"criteria":{"expression":"Age Class"}},{"code":{"text":"gender"},"criteria":{"expression":"Gender"}}]}]}}"#;
+
+    const STRATIFIER_CQL: &str = "library Retrieve\ndefine InInitialPopulation:\n  true\ndefine Birth:\n  Patient.birthDate\ndefine Age:\n  AgeInYearsAt(Birth)\ndefine \"Age Class\":\n  (Age div 10) * 10\ndefine Gender:\n  Patient.gender\n";
+
+    #[test]
+    fn test_select_stratifiers() {
+        let (_, cql) =
+            select_stratifiers(STRATIFIER_BODY, STRATIFIER_CQL, &["gender".to_string()]).unwrap();
+        // Age and Birth were only used by the removed "Age Class"
+        pretty_assertions::assert_eq!(
+            cql,
+            "library Retrieve\ndefine InInitialPopulation:\n  true\ndefine Gender:\n  Patient.gender\n"
+        );
+    }
+
+    #[test]
+    fn test_stratifiers() {
+        let stratifiers = ["gender".to_string(), "sample_kind".to_string()];
+        let body: serde_json::Value = serde_json::from_str(
+            &generate_body(
+                serde_json::from_str(MALE_OR_FEMALE).unwrap(),
+                Project::Bbmri,
+                Some(&stratifiers),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let groups: Vec<(&str, Vec<&str>)> = body["measure"]["group"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|group| {
+                (
+                    group["code"]["text"].as_str().unwrap(),
+                    group["stratifier"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|stratifier| stratifier["code"]["text"].as_str().unwrap())
+                        .collect(),
+                )
+            })
+            .collect();
+        pretty_assertions::assert_eq!(
+            groups,
+            vec![
+                ("patient", vec!["gender"]),
+                ("specimen", vec!["sample_kind"])
+            ]
+        );
+
+        let cql = String::from_utf8(
+            BASE64
+                .decode(body["lib"]["content"][0]["data"].as_str().unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(cql.contains("define Gender:"));
+        assert!(cql.contains("define function SampleType("));
+        assert!(cql.contains("define Specimen:"));
+        assert!(!cql.contains("define AgeClass:"));
+        assert!(!cql.contains("define Custodian:"));
+        assert!(!cql.contains("DiagnosisCode"));
+        assert!(!cql.contains("define Diagnosis:"));
+
+        assert!(matches!(
+            generate_body(
+                serde_json::from_str(MALE_OR_FEMALE).unwrap(),
+                Project::Bbmri,
+                Some(&["shoe_size".to_string()]),
+            ),
+            Err(FocusError::UnknownStratifier(_))
+        ));
+    }
+
     const CCE_MALE: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"AND","children":[{"key":"gender","operand":"OR","children":[{"key":"gender","type":"EQUALS","system":"","value":"male"}]}]}]},"id":"8bb53643-fe28-4556-a808-528a4274bea5"}"#;
     const CCE_ALIVE: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"AND","children":[{"key":"vitalStatusCS","operand":"OR","children":[{"key":"vitalStatusCS","type":"EQUALS","system":"https://www.cancercoreeurope.eu/fhir/core/CodeSystem/VitalStatusCS","value":"alive"}]}]}]},"id":"ba71c2d5-feb1-4649-800e-aaaac2e4bcb0"}"#;
 
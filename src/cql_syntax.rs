@@ -0,0 +1,699 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::errors::FocusError;
+
+/// Keywords starting the statements of a library, they are only recognized at the start of a line outside of brackets
+const STATEMENTS: [&str; 9] = [
+    "library",
+    "using",
+    "include",
+    "codesystem",
+    "valueset",
+    "code",
+    "parameter",
+    "context",
+    "define",
+];
+
+/// Statements requesters may write themselves in libraries with placeholders, everything else comes from the replacements
+const REQUESTER_STATEMENTS: [&str; 5] = ["library", "using", "include", "codesystem", "context"];
+
+/// Keywords that may only start statements, never appear in expressions
+const RESERVED: [&str; 4] = ["define", "parameter", "valueset", "codesystem"];
+
+/// Blaze only knows FHIR and the FHIRHelpers library, and Measures are evaluated per patient
+const ALLOWED_MODELS: [&str; 1] = ["FHIR"];
+const ALLOWED_INCLUDES: [&str; 1] = ["FHIRHelpers"];
+const ALLOWED_CONTEXTS: [&str; 1] = ["Patient"];
+
+/// An error in a CQL library, lines and columns start at 1
+#[derive(Debug, PartialEq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Kind {
+    Identifier,
+    QuotedIdentifier,
+    String,
+    Number,
+    DateTime,
+    Symbol,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+    line: usize,
+    column: usize,
+    first_on_line: bool,
+    depth: usize,
+    offset: usize,
+}
+
+impl Token<'_> {
+    fn error(&self, message: impl Into<String>) -> SyntaxError {
+        SyntaxError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    fn is(&self, kind: Kind, text: &str) -> bool {
+        self.kind == kind && self.text == text
+    }
+
+    /// Name of an identifier, without the quotes of a quoted one
+    fn name(&self) -> Option<&str> {
+        match self.kind {
+            Kind::Identifier => Some(self.text),
+            Kind::QuotedIdentifier => Some(&self.text[1..self.text.len() - 1]),
+            _ => None,
+        }
+    }
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    position: usize,
+    line: usize,
+    column: usize,
+    first_on_line: bool,
+    tokens: Vec<Token<'a>>,
+    errors: Vec<SyntaxError>,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.text[self.position..].chars().nth(offset)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.position += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+            self.first_on_line = true;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn bump_while(&mut self, condition: impl Fn(char) -> bool) {
+        while self.peek(0).is_some_and(&condition) {
+            self.bump();
+        }
+    }
+
+    /// Consumes a string, quoted identifier or comment up to `end`, false if it never ends
+    fn bump_until(&mut self, end: &str, escapes: bool) -> bool {
+        while !self.text[self.position..].starts_with(end) {
+            match self.bump() {
+                None => return false,
+                Some('\\') if escapes => {
+                    self.bump();
+                }
+                Some(_) => {}
+            }
+        }
+        for _ in end.chars() {
+            self.bump();
+        }
+        true
+    }
+
+    fn lex(mut self) -> (Vec<Token<'a>>, Vec<SyntaxError>) {
+        while let Some(c) = self.peek(0) {
+            let (start, line, column) = (self.position, self.line, self.column);
+            let error = |message: String| SyntaxError {
+                line,
+                column,
+                message,
+            };
+
+            let kind = match c {
+                _ if c.is_whitespace() => {
+                    self.bump();
+                    continue;
+                }
+                '/' if self.peek(1) == Some('/') => {
+                    self.bump_while(|c| c != '\n');
+                    continue;
+                }
+                '/' if self.peek(1) == Some('*') => {
+                    self.bump();
+                    self.bump();
+                    if !self.bump_until("*/", false) {
+                        self.errors.push(error("Comment is never closed".into()));
+                    }
+                    continue;
+                }
+                '{' if self.text[start..].starts_with("{{") => {
+                    let placeholder = self.text[start + 2..]
+                        .split_once("}}")
+                        .map(|(name, _)| name)
+                        .filter(|name| name.chars().all(|c| c.is_alphanumeric() || c == '_'));
+                    if let Some(name) = placeholder {
+                        self.errors
+                            .push(error(format!("Placeholder {{{{{}}}}} is not filled", name)));
+                        for _ in 0..name.chars().count() + 4 {
+                            self.bump();
+                        }
+                        continue;
+                    }
+                    self.bump();
+                    Kind::Symbol
+                }
+                '\'' | '"' | '`' => {
+                    self.bump();
+                    if !self.bump_until(&c.to_string(), true) {
+                        self.errors.push(error(match c {
+                            '\'' => "String is never closed".into(),
+                            _ => "Quoted identifier is never closed".into(),
+                        }));
+                        break;
+                    }
+                    match c {
+                        '\'' => Kind::String,
+                        _ => Kind::QuotedIdentifier,
+                    }
+                }
+                '@' => {
+                    self.bump();
+                    self.bump_while(|c| c.is_ascii_digit() || "-:.+TZ".contains(c));
+                    if self.position == start + 1 {
+                        self.errors
+                            .push(error("Date or time expected after @".into()));
+                        continue;
+                    }
+                    Kind::DateTime
+                }
+                _ if c.is_ascii_digit() => {
+                    self.bump_while(|c| c.is_ascii_digit());
+                    if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit())
+                    {
+                        self.bump();
+                        self.bump_while(|c| c.is_ascii_digit());
+                    }
+                    Kind::Number
+                }
+                _ if c.is_alphabetic() || c == '_' || c == '$' => {
+                    self.bump();
+                    self.bump_while(|c| c.is_alphanumeric() || c == '_');
+                    Kind::Identifier
+                }
+                '<' | '>' | '!' if self.peek(1) == Some('=') => {
+                    self.bump();
+                    self.bump();
+                    Kind::Symbol
+                }
+                '!' if self.peek(1) == Some('~') => {
+                    self.bump();
+                    self.bump();
+                    Kind::Symbol
+                }
+                _ if "()[]{},.:+-*/^&|=<>~".contains(c) => {
+                    self.bump();
+                    Kind::Symbol
+                }
+                _ => {
+                    self.bump();
+                    self.errors
+                        .push(error(format!("Unexpected character {:?}", c)));
+                    continue;
+                }
+            };
+
+            self.tokens.push(Token {
+                kind,
+                text: &self.text[start..self.position],
+                line,
+                column,
+                first_on_line: std::mem::take(&mut self.first_on_line),
+                depth: 0,
+                offset: start,
+            });
+        }
+        (self.tokens, self.errors)
+    }
+}
+
+fn tokenize(text: &str) -> (Vec<Token<'_>>, Vec<SyntaxError>) {
+    Lexer {
+        text,
+        position: 0,
+        line: 1,
+        column: 1,
+        first_on_line: true,
+        tokens: Vec::new(),
+        errors: Vec::new(),
+    }
+    .lex()
+}
+
+/// Sets the bracket depth of the tokens and reports brackets that don't match
+fn check_brackets(tokens: &mut [Token], errors: &mut Vec<SyntaxError>) {
+    let mut open: Vec<usize> = Vec::new();
+    for index in 0..tokens.len() {
+        tokens[index].depth = open.len();
+        let token = tokens[index];
+        if token.kind != Kind::Symbol {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => open.push(index),
+            ")" | "]" | "}" => {
+                let Some(opener_index) = open.pop() else {
+                    errors.push(token.error(format!("{} without opening bracket", token.text)));
+                    continue;
+                };
+                tokens[index].depth = open.len();
+                let opener = tokens[opener_index];
+                let expected = match opener.text {
+                    "(" => ")",
+                    "[" => "]",
+                    _ => "}",
+                };
+                // intervals may be closed or open on either side, as in Interval[1, 10)
+                let interval = opener_index > 0
+                    && tokens[opener_index - 1].is(Kind::Identifier, "Interval")
+                    && opener.text != "{"
+                    && token.text != "}";
+                if token.text != expected && !interval {
+                    errors.push(token.error(format!(
+                        "{} closes {} opened at {}:{}",
+                        token.text, opener.text, opener.line, opener.column
+                    )));
+                }
+            }
+            _ => {}
+        }
+    }
+    for index in open {
+        let opener = tokens[index];
+        errors.push(opener.error(format!("{} is never closed", opener.text)));
+    }
+}
+
+/// The tokens of one statement, consumed while checking it
+struct Statement<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    position: usize,
+}
+
+impl<'a> Statement<'_, 'a> {
+    fn keyword(&self) -> &Token<'a> {
+        &self.tokens[0]
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token<'a>, SyntaxError> {
+        let token = self.tokens.get(self.position).copied().ok_or_else(|| {
+            self.tokens[self.tokens.len() - 1].error(format!(
+                "{} expected after {}",
+                expected,
+                self.tokens[self.tokens.len() - 1].text
+            ))
+        })?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn name(&mut self) -> Result<&'a str, SyntaxError> {
+        let token = self.next("Name")?;
+        match token.kind {
+            Kind::Identifier => Ok(token.text),
+            Kind::QuotedIdentifier => Ok(&token.text[1..token.text.len() - 1]),
+            _ => Err(token.error(format!("Name expected, found {}", token.text))),
+        }
+    }
+
+    fn string(&mut self) -> Result<(), SyntaxError> {
+        let token = self.next("String")?;
+        match token.kind {
+            Kind::String => Ok(()),
+            _ => Err(token.error(format!("String expected, found {}", token.text))),
+        }
+    }
+
+    /// Consumes the symbol or keyword, which must be next
+    fn expect(&mut self, text: &str) -> Result<(), SyntaxError> {
+        let token = self.next(text)?;
+        match token.kind {
+            Kind::Symbol | Kind::Identifier if token.text == text => Ok(()),
+            _ => Err(token.error(format!("{} expected, found {}", text, token.text))),
+        }
+    }
+
+    /// Consumes the keyword if it is next
+    fn optional(&mut self, keyword: &str) -> bool {
+        let found = self
+            .tokens
+            .get(self.position)
+            .is_some_and(|token| token.is(Kind::Identifier, keyword));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn end(&self) -> Result<(), SyntaxError> {
+        match self.tokens.get(self.position) {
+            None => Ok(()),
+            Some(token) => Err(token.error(format!(
+                "Unexpected {} in {} statement",
+                token.text,
+                self.keyword().text
+            ))),
+        }
+    }
+}
+
+/// Checks a statement, returns the name it defines for define statements
+fn check_statement(statement: &mut Statement) -> Result<Option<String>, SyntaxError> {
+    let keyword = *statement.keyword();
+    statement.position = 1;
+    match keyword.text {
+        "library" | "using" | "include" => {
+            let name_token = statement.tokens.get(1).copied();
+            let name = statement.name()?;
+            if statement.optional("version") {
+                statement.string()?;
+            }
+            if keyword.text == "include" && statement.optional("called") {
+                statement.name()?;
+            }
+            statement.end()?;
+            let allowed: &[&str] = match keyword.text {
+                "using" => &ALLOWED_MODELS,
+                "include" => &ALLOWED_INCLUDES,
+                _ => return Ok(None),
+            };
+            if !allowed.contains(&name) {
+                return Err(name_token
+                    .unwrap_or(keyword)
+                    .error(format!("{} {} is not allowed", keyword.text, name)));
+            }
+        }
+        "codesystem" | "valueset" => {
+            statement.name()?;
+            statement.expect(":")?;
+            statement.string()?;
+            if statement.optional("version") {
+                statement.string()?;
+            }
+            statement.end()?;
+        }
+        "code" => {
+            statement.name()?;
+            statement.expect(":")?;
+            statement.string()?;
+            statement.expect("from")?;
+            statement.name()?;
+            if statement.optional("display") {
+                statement.string()?;
+            }
+            statement.end()?;
+        }
+        "context" => {
+            let name_token = statement.next("Context")?;
+            let name = name_token.name().unwrap_or(name_token.text);
+            statement.end()?;
+            if !ALLOWED_CONTEXTS.contains(&name) {
+                return Err(name_token.error(format!("context {} is not allowed", name)));
+            }
+        }
+        "parameter" => {
+            statement.name()?;
+        }
+        "define" => {
+            if !statement.optional("public") {
+                statement.optional("private");
+            }
+            statement.optional("fluent");
+            let function = statement.optional("function");
+            let name = statement.name()?;
+            if function {
+                statement.expect("(")?;
+                let closing = statement.tokens[statement.position..]
+                    .iter()
+                    .position(|token| token.is(Kind::Symbol, ")") && token.depth == keyword.depth)
+                    .ok_or_else(|| keyword.error("Parameters of function are never closed"))?;
+                statement.position += closing + 1;
+                if statement.optional("returns") {
+                    while statement
+                        .tokens
+                        .get(statement.position)
+                        .is_some_and(|token| !token.is(Kind::Symbol, ":"))
+                    {
+                        statement.position += 1;
+                    }
+                }
+            }
+            statement.expect(":")?;
+            statement.next("Expression")?;
+            return Ok((!function).then(|| name.to_string()));
+        }
+        _ => unreachable!("only statement keywords start statements"),
+    }
+    Ok(None)
+}
+
+/// Finds syntax errors and constructs Blaze can't or must not run in a CQL library
+pub fn check(library: &str) -> Vec<SyntaxError> {
+    let (mut tokens, mut errors) = tokenize(library);
+    check_brackets(&mut tokens, &mut errors);
+
+    let starts: Vec<usize> = tokens
+        .iter()
+        .enumerate()
+        .filter(|(index, token)| {
+            token.kind == Kind::Identifier
+                && token.depth == 0
+                && token.first_on_line
+                && STATEMENTS.contains(&token.text)
+                && (*index == 0 || !tokens[index - 1].is(Kind::Symbol, "."))
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if let Some(token) = tokens.first() {
+        if starts.first() != Some(&0) {
+            errors.push(token.error(format!("Statement expected, found {}", token.text)));
+        }
+    }
+
+    let mut defined = HashSet::new();
+    for (number, start) in starts.iter().enumerate() {
+        let end = starts.get(number + 1).copied().unwrap_or(tokens.len());
+        let keyword = tokens[*start];
+        if keyword.text == "library" && number != 0 {
+            errors.push(keyword.error("library must be the first statement"));
+        }
+        let mut statement = Statement {
+            tokens: &tokens[*start..end],
+            position: 0,
+        };
+        match check_statement(&mut statement) {
+            Ok(Some(name)) => {
+                if !defined.insert(name.clone()) {
+                    errors.push(keyword.error(format!("{} is defined more than once", name)));
+                }
+            }
+            Ok(None) => {}
+            Err(error) => errors.push(error),
+        }
+    }
+
+    errors.sort_by_key(|error| (error.line, error.column));
+    errors
+}
+
+/// Fails with all errors found in a CQL library before it is sent to Blaze
+pub fn ensure_valid(library: &str) -> Result<(), FocusError> {
+    let errors = check(library);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(FocusError::CqlSyntaxError(
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; "),
+    ))
+}
+
+/// Whether a replacement leaves its last definition open for the requester to finish, as in `define InInitialPopulation:`
+fn is_open(replacement: &str) -> bool {
+    let (tokens, _) = tokenize(replacement);
+    tokens.last().is_some_and(|token| {
+        token.is(Kind::Symbol, ":")
+            || token.is(Kind::Identifier, "and")
+            || token.is(Kind::Identifier, "or")
+    })
+}
+
+/// Finds what a requester must not write in a library with placeholders: statements other than the library header,
+/// placeholders that don't start a line, and expressions not finishing a definition a placeholder left open
+pub fn check_tampering(library: &str, placeholders: &HashMap<&str, &str>) -> Vec<SyntaxError> {
+    let (mut tokens, mut errors) = tokenize(library);
+    check_brackets(&mut tokens, &mut errors);
+
+    // the placeholder whose definition the tokens belong to, None in the header
+    let mut current: Option<(&str, bool)> = None;
+    for (index, token) in tokens.iter().enumerate() {
+        let starts_statement = token.kind == Kind::Identifier
+            && token.depth == 0
+            && token.first_on_line
+            && (index == 0 || !tokens[index - 1].is(Kind::Symbol, "."));
+
+        if token.kind == Kind::Identifier && placeholders.contains_key(token.text) {
+            if !starts_statement {
+                errors.push(token.error(format!("Placeholder {} must start a line", token.text)));
+            }
+            current = Some((token.text, is_open(placeholders[token.text])));
+        } else if starts_statement && STATEMENTS.contains(&token.text) {
+            if !REQUESTER_STATEMENTS.contains(&token.text) {
+                errors.push(token.error(format!(
+                    "{} statements are not allowed, only {} and placeholders",
+                    token.text,
+                    REQUESTER_STATEMENTS.join(", ")
+                )));
+            } else if current.is_some() {
+                errors.push(token.error(format!(
+                    "{} statements must come before the placeholders",
+                    token.text
+                )));
+            }
+            current = None;
+        } else if token.kind == Kind::Identifier && RESERVED.contains(&token.text) {
+            errors.push(token.error(format!("{} must start a statement", token.text)));
+        } else if let Some((placeholder, false)) = current {
+            errors.push(token.error(format!(
+                "Unexpected {} after placeholder {}, which takes no expression",
+                token.text, placeholder
+            )));
+            // one error per placeholder is enough
+            current = Some((placeholder, true));
+        } else if current.is_none() && index == 0 {
+            errors.push(token.error(format!("Statement expected, found {}", token.text)));
+        }
+    }
+
+    errors.sort_by_key(|error| (error.line, error.column));
+    errors
+}
+
+/// Replaces the placeholders written as identifiers, leaving equal text in strings, comments and longer names alone
+pub fn replace_placeholders(library: &str, placeholders: &HashMap<&str, &str>) -> String {
+    let (tokens, _) = tokenize(library);
+    let mut replaced = String::with_capacity(library.len());
+    let mut copied = 0;
+    for token in tokens {
+        if let (Kind::Identifier, Some(replacement)) = (token.kind, placeholders.get(token.text)) {
+            replaced.push_str(&library[copied..token.offset]);
+            replaced.push_str(replacement);
+            copied = token.offset + token.text.len();
+        }
+    }
+    replaced.push_str(&library[copied..]);
+    replaced
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LIBRARY: &str = "library Retrieve
+using FHIR version '4.0.0'
+include FHIRHelpers version '4.0.0'
+
+codesystem loinc: 'http://loinc.org'
+
+context Patient
+
+define Gender:
+if (Patient.gender is null) then 'unknown' else Patient.gender
+
+define function DiagnosisCode(condition FHIR.Condition):
+condition.code.coding.where(system = 'http://fhir.de/CodeSystem/bfarm/icd-10-gm').code.first()
+
+define InInitialPopulation:
+exists [Observation: Code '29463-7' from loinc] O where O.value in Interval[50 'kg', 100 'kg')
+";
+
+    fn messages(library: &str) -> Vec<String> {
+        check(library).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_valid_libraries() {
+        pretty_assertions::assert_eq!(messages(LIBRARY), Vec::<String>::new());
+
+        for entry in std::fs::read_dir("resources/test").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "cql")
+                && !path.ends_with("query_bbmri_placeholders.cql")
+            {
+                let library = std::fs::read_to_string(&path).unwrap();
+                pretty_assertions::assert_eq!(
+                    messages(&library),
+                    Vec::<String>::new(),
+                    "{}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        pretty_assertions::assert_eq!(
+            messages(&LIBRARY.replace("(Patient.gender is null)", "(Patient.gender is null")),
+            vec!["10:4: ( is never closed"]
+        );
+        pretty_assertions::assert_eq!(
+            messages(&LIBRARY.replace("from loinc]", "from {{A1}}]")),
+            vec!["16:42: Placeholder {{A1}} is not filled"]
+        );
+        pretty_assertions::assert_eq!(
+            messages(&LIBRARY.replace("define Gender:", "define Gender")),
+            vec!["10:1: : expected, found if"]
+        );
+        pretty_assertions::assert_eq!(
+            messages(&LIBRARY.replace("100 'kg')", "100 'kg)")),
+            vec!["16:76: [ is never closed", "16:90: String is never closed"]
+        );
+        pretty_assertions::assert_eq!(
+            messages(&LIBRARY.replace("define InInitialPopulation", "define Gender")),
+            vec!["15:1: Gender is defined more than once"]
+        );
+    }
+
+    #[test]
+    fn test_disallowed_constructs() {
+        pretty_assertions::assert_eq!(
+            messages(&LIBRARY.replace("include FHIRHelpers", "include Secrets")),
+            vec!["3:9: include Secrets is not allowed"]
+        );
+        pretty_assertions::assert_eq!(
+            messages(&LIBRARY.replace("context Patient", "context Unfiltered")),
+            vec!["7:9: context Unfiltered is not allowed"]
+        );
+        assert!(ensure_valid(LIBRARY).is_ok());
+        assert!(ensure_valid(&LIBRARY.replace("using FHIR", "using QDM")).is_err());
+    }
+}
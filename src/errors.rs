@@ -7,10 +7,18 @@ pub enum FocusError {
     UnableToPostLibrary(reqwest::Error),
     #[error("Unable to post FHIR Measure: {0}")]
     UnableToPostMeasure(reqwest::Error),
+    #[error("Unable to search FHIR resources: {0}")]
+    UnableToSearchResources(reqwest::Error),
+    #[error("Unable to delete FHIR resources: {0}")]
+    UnableToDeleteResources(String),
     #[error("FHIR Measure evaluation error in Reqwest: {0}")]
     MeasureEvaluationErrorReqwest(reqwest::Error),
     #[error("FHIR Measure evaluation error in Blaze: {0}")]
     MeasureEvaluationErrorBlaze(String),
+    #[error("FHIR Measure evaluation error in FHIR server: {0}")]
+    MeasureEvaluationErrorFhir(String),
+    #[error("FHIR Measure or its Library not found: {0}")]
+    MeasureNotFound(String),
     #[error("CQL query error")]
     CQLQueryError,
     #[error("Unable to retrieve tasks from Beam: {0}")]
@@ -29,6 +37,8 @@ pub enum FocusError {
     ParsingError(String),
     #[error("CQL tampered with: {0}")]
     CQLTemperedWithError(String),
+    #[error("Invalid CQL: {0}")]
+    CqlSyntaxError(String),
     #[error("Laplace error: {0}")]
     LaplaceError(laplace_rs::errors::LaplaceError),
     #[error("Deserialization error: {0}")]
@@ -57,6 +67,12 @@ pub enum FocusError {
     AstOperatorValueMismatch(String),
     #[error("Invalid date format: {0}")]
     AstInvalidDateFormat(String),
+    #[error("Invalid AST: {0}")]
+    AstInvalid(String),
+    #[error("Invalid unit in AST: {0}")]
+    AstInvalidUnit(String),
+    #[error("Code system alias used for different code systems: {0}")]
+    AstCodeSystemConflict(String),
     #[error("Invalid Header Value: {0}")]
     InvalidHeaderValue(header::InvalidHeaderValue),
     #[error("Missing Exporter Endpoint")]
@@ -76,6 +92,20 @@ pub enum FocusError {
     ErrorExecutingSqlQuery(sqlx::Error),
     #[error("Unknown project: {0}")]
     UnknownProject(String),
+    #[error("Invalid project definition: {0}")]
+    InvalidProjectDefinition(String),
+    #[error("Invalid terminology: {0}")]
+    InvalidTerminology(String),
+    #[error("Unknown stratifier: {0}")]
+    UnknownStratifier(String),
+    #[error("Requester {0} is not allowed to explain queries")]
+    ExplainNotAllowed(String),
+    #[error("Queries to {0} endpoints can't be explained")]
+    ExplainNotSupported(String),
+    #[error("Invalid measure evaluation period: {0}")]
+    InvalidMeasurePeriod(String),
+    #[error("Requester {0} is not allowed to set the measure evaluation period")]
+    MeasurePeriodNotAllowed(String),
 }
 
 impl FocusError {
@@ -87,6 +117,10 @@ impl FocusError {
             DecodeError(_) | ParsingError(_) | SerdeParsingError(_) => "Cannot parse query.",
             LaplaceError(_) => "Cannot obfuscate result.",
             UnknownProject(_) => "Unknown project specified.",
+            CqlSyntaxError(_) => "Invalid CQL in query.",
+            ExplainNotAllowed(_) => "Not allowed to explain queries.",
+            InvalidMeasurePeriod(_) => "Invalid evaluation period.",
+            MeasurePeriodNotAllowed(_) => "Not allowed to set the evaluation period.",
             _ => "Failed to execute query.",
         }
     }
@@ -71,6 +71,11 @@ pub fn build_eucaim_sql_query(ast: ast::Ast) -> Result<String, FocusError> {
     criteria.insert(0, "".to_string());
     criteria.insert(1, "".to_string());
 
+    if ast.ast.operand == ast::Operand::Not {
+        error!("NOT found as top level operator, negation not supported");
+        return Err(FocusError::EucaimQueryGenerationError);
+    }
+
     let children = ast.ast.children;
 
     if children.len() > 1 {
@@ -82,16 +87,30 @@ pub fn build_eucaim_sql_query(ast: ast::Ast) -> Result<String, FocusError> {
         // will be either 0 or 1
         match child {
             ast::Child::Operation(operation) => {
-                if operation.operand == ast::Operand::Or {
-                    error!("OR found as first level operator");
-                    return Err(FocusError::EucaimQueryGenerationError);
+                match operation.operand {
+                    ast::Operand::Or => {
+                        error!("OR found as first level operator");
+                        return Err(FocusError::EucaimQueryGenerationError);
+                    }
+                    ast::Operand::Not => {
+                        error!("NOT found as first level operator, negation not supported");
+                        return Err(FocusError::EucaimQueryGenerationError);
+                    }
+                    ast::Operand::And => {}
                 }
                 for grandchild in operation.children {
                     match grandchild {
                         ast::Child::Operation(operation) => {
-                            if operation.operand == ast::Operand::And {
-                                error!("AND found as second level operator");
-                                return Err(FocusError::EucaimQueryGenerationError);
+                            match operation.operand {
+                                ast::Operand::And => {
+                                    error!("AND found as second level operator");
+                                    return Err(FocusError::EucaimQueryGenerationError);
+                                }
+                                ast::Operand::Not => {
+                                    error!("NOT found as second level operator, negation not supported");
+                                    return Err(FocusError::EucaimQueryGenerationError);
+                                }
+                                ast::Operand::Or => {}
                             }
                             let greatgrandchildren = operation.children;
                             if greatgrandchildren.len() > 1 {
@@ -107,6 +126,14 @@ pub fn build_eucaim_sql_query(ast: ast::Ast) -> Result<String, FocusError> {
                                         );
                                         return Err(FocusError::EucaimQueryGenerationError);
                                     }
+                                    ast::Child::Temporal(_) => {
+                                        error!("Temporal relation found, temporal relations not supported");
+                                        return Err(FocusError::EucaimQueryGenerationError);
+                                    }
+                                    ast::Child::Entity(_) => {
+                                        error!("Entity found, grouping criteria by entity not supported");
+                                        return Err(FocusError::EucaimQueryGenerationError);
+                                    }
                                     ast::Child::Condition(condition) => {
                                         let category_maybe =
                                             CRITERION_CATEGORY.get(&(condition.key).as_str());
@@ -115,6 +142,15 @@ pub fn build_eucaim_sql_query(ast: ast::Ast) -> Result<String, FocusError> {
                                             let snippet_maybe =
                                                 CRITERION_SNIPPET.get(&(condition.key).as_str());
                                             if let Some(snippet) = snippet_maybe {
+                                                if !matches!(
+                                                    condition.type_,
+                                                    ast::ConditionType::Equals
+                                                ) {
+                                                    return Err(FocusError::AstOperatorValueMismatch(format!("EUCAIM SQL queries only support operator EQUALS, not {:?} for {}", condition.type_, condition.key)));
+                                                }
+                                                if condition.count.is_some() {
+                                                    return Err(FocusError::AstOperatorValueMismatch(format!("EUCAIM SQL queries don't support counts, found one for {}", condition.key)));
+                                                }
                                                 match condition.value {
                                                     ast::ConditionValue::String(value) => {
                                                         let criterion_maybe =
@@ -139,11 +175,8 @@ pub fn build_eucaim_sql_query(ast: ast::Ast) -> Result<String, FocusError> {
                                                             }
                                                         }
                                                     }
-                                                    _ => {
-                                                        error!("The only supported condition value type is string");
-                                                        return Err(
-                                                            FocusError::EucaimQueryGenerationError,
-                                                        );
+                                                    other => {
+                                                        return Err(FocusError::AstOperatorValueMismatch(format!("Operator EQUALS can only be used for strings in EUCAIM SQL queries, not for {:?}", other)));
                                                     }
                                                 }
                                             }
@@ -152,6 +185,16 @@ pub fn build_eucaim_sql_query(ast: ast::Ast) -> Result<String, FocusError> {
                                 }
                             }
                         }
+                        ast::Child::Entity(_) => {
+                            // must be operation
+                            error!("Entity found as second level child");
+                            return Err(FocusError::EucaimQueryGenerationError);
+                        }
+                        ast::Child::Temporal(_) => {
+                            // must be operation
+                            error!("Temporal relation found as second level child");
+                            return Err(FocusError::EucaimQueryGenerationError);
+                        }
                         ast::Child::Condition(_) => {
                             // must be operation
                             error!("Condition found as second level child");
@@ -160,6 +203,16 @@ pub fn build_eucaim_sql_query(ast: ast::Ast) -> Result<String, FocusError> {
                     }
                 }
             }
+            ast::Child::Entity(_) => {
+                // must be operation
+                error!("Entity found as first level child");
+                return Err(FocusError::EucaimQueryGenerationError);
+            }
+            ast::Child::Temporal(_) => {
+                // must be operation
+                error!("Temporal relation found as first level child");
+                return Err(FocusError::EucaimQueryGenerationError);
+            }
             ast::Child::Condition(_) => {
                 // must be operation
                 error!("Condition found as first level child");
@@ -203,6 +256,12 @@ mod test {
 
     const TOO_MUCH: &str = r#"{"ast":{"children":[{"children":[{"children":[{"key":"SNOMEDCT263495000","system":"","type":"EQUALS","value":"SNOMEDCT248153007"},{"key":"SNOMEDCT263495000","system":"","type":"EQUALS","value":"SNOMEDCT248152002"}],"operand":"OR"},{"children":[{"key":"SNOMEDCT439401001","system":"urn:snomed-org/sct","type":"EQUALS","value":"SNOMEDCT399068003"},{"key":"SNOMEDCT439401001","system":"urn:snomed-org/sct","type":"EQUALS","value":"SNOMEDCT254837009"}],"operand":"OR"},{"children":[{"key":"RID10311","system":"urn:oid:2.16.840.1.113883.6.256","type":"EQUALS","value":"RID10312"},{"key":"RID10311","system":"urn:oid:2.16.840.1.113883.6.256","type":"EQUALS","value":"RID10337"}],"operand":"OR"},{"children":[{"key":"SNOMEDCT123037004","system":"urn:snomed-org/sct","type":"EQUALS","value":"SNOMEDCT76752008"},{"key":"SNOMEDCT123037004","system":"urn:snomed-org/sct","type":"EQUALS","value":"SNOMEDCT41216001"}],"operand":"OR"},{"children":[{"key":"C25392","system":"http://bioontology.org/projects/ontologies/birnlex","type":"EQUALS","value":"birnlex_3065"},{"key":"C25392","system":"http://bioontology.org/projects/ontologies/birnlex","type":"EQUALS","value":"birnlex_3067"}],"operand":"OR"}],"operand":"AND"}],"operand":"OR"},"id":"c57e075c-19de-4c5a-ba9c-b8f697a98dfc__search__c57e075c-19de-4c5a-ba9c-b8f697a98dfc"}"#;
 
+    const NOT: &str = r#"{"ast":{"children":[{"children":[{"children":[{"key":"SNOMEDCT263495000","system":"","type":"EQUALS","value":"SNOMEDCT248153007"}],"operand":"NOT"}],"operand":"AND"}],"operand":"OR"},"id":"66b8bbf4-ded2-4f94-87ab-3a3ca2f4edc0__search__66b8bbf4-ded2-4f94-87ab-3a3ca2f4edc0"}"#;
+
+    const NOT_EQUALS: &str = r#"{"ast":{"children":[{"children":[{"children":[{"key":"SNOMEDCT263495000","system":"","type":"NOT_EQUALS","value":"SNOMEDCT248153007"}],"operand":"OR"}],"operand":"AND"}],"operand":"OR"},"id":"66b8bbf4-ded2-4f94-87ab-3a3ca2f4edc0__search__66b8bbf4-ded2-4f94-87ab-3a3ca2f4edc0"}"#;
+
+    const EQUALS_ARRAY: &str = r#"{"ast":{"children":[{"children":[{"children":[{"key":"SNOMEDCT263495000","system":"","type":"EQUALS","value":["SNOMEDCT248153007"]}],"operand":"OR"}],"operand":"AND"}],"operand":"OR"},"id":"66b8bbf4-ded2-4f94-87ab-3a3ca2f4edc0__search__66b8bbf4-ded2-4f94-87ab-3a3ca2f4edc0"}"#;
+
     #[test]
     fn test_build_sql_empty() {
         let sql = build_eucaim_sql_query(serde_json::from_str(EMPTY).unwrap()).unwrap();
@@ -219,4 +278,21 @@ mod test {
     fn test_build_sql_too_much() {
         assert!(build_eucaim_sql_query(serde_json::from_str(TOO_MUCH).unwrap(),).is_err());
     }
+
+    #[test]
+    fn test_build_sql_not() {
+        assert!(build_eucaim_sql_query(serde_json::from_str(NOT).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_build_sql_operator_value_mismatch() {
+        assert!(matches!(
+            build_eucaim_sql_query(serde_json::from_str(NOT_EQUALS).unwrap()),
+            Err(FocusError::AstOperatorValueMismatch(_))
+        ));
+        assert!(matches!(
+            build_eucaim_sql_query(serde_json::from_str(EQUALS_ARRAY).unwrap()),
+            Err(FocusError::AstOperatorValueMismatch(_))
+        ));
+    }
 }
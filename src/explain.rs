@@ -0,0 +1,200 @@
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::ast;
+use crate::blaze::CqlQuery;
+use crate::config::EndpointType;
+use crate::cql;
+use crate::errors::FocusError;
+use crate::eucaim_api;
+use crate::intermediate_rep;
+use crate::util::base64_decode;
+use crate::validation;
+
+#[cfg(feature = "query-sql")]
+use crate::eucaim_sql;
+
+/// What Focus would run against its endpoint for a query, instead of running it
+#[derive(Debug, Serialize)]
+#[serde(tag = "language", rename_all = "lowercase")]
+pub enum Explanation {
+    Cql {
+        library: String,
+        measure: Value,
+    },
+    Sql {
+        query: String,
+    },
+    Url {
+        url: String,
+    },
+    /// The query mediator of OMOP endpoints is sent the AST as is
+    Ast {
+        ast: ast::Ast,
+    },
+}
+
+impl Explanation {
+    /// The CQL library of a Blaze query, decoded so it can be read
+    pub fn from_cql_query(query: CqlQuery) -> Result<Self, FocusError> {
+        let encoded = query.lib["content"][0]["data"].as_str().ok_or_else(|| {
+            FocusError::ParsingError("Library has no CQL content to explain".into())
+        })?;
+        let library = String::from_utf8(base64_decode(encoded)?)
+            .map_err(|e| FocusError::ParsingError(e.to_string()))?;
+        Ok(Explanation::Cql {
+            library,
+            measure: query.measure,
+        })
+    }
+}
+
+/// Builds the query an endpoint of the given type would be sent for an AST, without touching the endpoint
+pub fn explain(
+    ast: ast::Ast,
+    project: &str,
+    stratifiers: Option<&[String]>,
+    endpoint_type: EndpointType,
+    endpoint_url: Url,
+) -> Result<Explanation, FocusError> {
+    match endpoint_type {
+        EndpointType::Blaze | EndpointType::Fhir => Explanation::from_cql_query(
+            serde_json::from_str(&cql::generate_body(ast, project.parse()?, stratifiers)?)?,
+        ),
+        #[cfg(feature = "query-sql")]
+        EndpointType::BlazeAndSql => Explanation::from_cql_query(serde_json::from_str(
+            &cql::generate_body(ast, project.parse()?, stratifiers)?,
+        )?),
+        EndpointType::Omop => {
+            validation::ensure_valid(&ast, None)?;
+            Ok(Explanation::Ast { ast })
+        }
+        EndpointType::EucaimApi => Ok(Explanation::Url {
+            url: eucaim_api::build_eucaim_api_query_url(
+                endpoint_url,
+                intermediate_rep::prepare(ast)?,
+            )?,
+        }),
+        #[cfg(feature = "query-sql")]
+        EndpointType::EucaimSql => Ok(Explanation::Sql {
+            query: eucaim_sql::build_eucaim_sql_query(intermediate_rep::prepare(ast)?)?,
+        }),
+        #[cfg(feature = "query-sql")]
+        EndpointType::Sql => Err(FocusError::ExplainNotSupported(endpoint_type.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GENDER_MALE: &str = r#"{"ast":{"operand":"OR","children":[{"key":"gender","type":"EQUALS","value":"male"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const EUCAIM_MALE: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"AND","children":[{"operand":"OR","children":[{"key":"SNOMEDCT263495000","type":"EQUALS","system":"","value":"SNOMEDCT248153007"}]}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    // the same query as EUCAIM_MALE, nested deeper and with the criterion twice
+    const EUCAIM_MALE_NESTED: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"AND","children":[{"operand":"AND","children":[{"operand":"OR","children":[{"key":"SNOMEDCT263495000","type":"EQUALS","system":"","value":"SNOMEDCT248153007"}]}]},{"operand":"OR","children":[{"key":"SNOMEDCT263495000","type":"EQUALS","system":"","value":"SNOMEDCT248153007"}]}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    fn url() -> Url {
+        Url::parse("http://localhost:8080/search").unwrap()
+    }
+
+    #[test]
+    fn test_explain() {
+        let Explanation::Cql { library, measure } = explain(
+            serde_json::from_str(GENDER_MALE).unwrap(),
+            "bbmri",
+            None,
+            EndpointType::Blaze,
+            url(),
+        )
+        .unwrap() else {
+            panic!("Blaze queries are explained as CQL");
+        };
+        assert!(library.contains("Patient.gender = 'male'"));
+        pretty_assertions::assert_eq!(measure["resourceType"], "Measure");
+
+        assert!(matches!(
+            explain(
+                serde_json::from_str(GENDER_MALE).unwrap(),
+                "bbmri",
+                None,
+                EndpointType::Omop,
+                url()
+            ),
+            Ok(Explanation::Ast { .. })
+        ));
+
+        assert!(matches!(
+            explain(
+                serde_json::from_str(EUCAIM_MALE).unwrap(),
+                "eucaim",
+                None,
+                EndpointType::EucaimApi,
+                url()
+            ),
+            Ok(Explanation::Url { url }) if url == "http://localhost:8080/search?gender=male"
+        ));
+
+        assert!(explain(
+            serde_json::from_str(GENDER_MALE).unwrap(),
+            "nonexistent",
+            None,
+            EndpointType::Blaze,
+            url()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_explain_normalized() {
+        let explain_eucaim = |ast: &str, endpoint_type: EndpointType| {
+            serde_json::to_string(
+                &explain(
+                    serde_json::from_str(ast).unwrap(),
+                    "eucaim",
+                    None,
+                    endpoint_type,
+                    url(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        let mut endpoint_types = vec![EndpointType::EucaimApi];
+        #[cfg(feature = "query-sql")]
+        endpoint_types.push(EndpointType::EucaimSql);
+        for endpoint_type in endpoint_types {
+            pretty_assertions::assert_eq!(
+                explain_eucaim(EUCAIM_MALE_NESTED, endpoint_type),
+                explain_eucaim(EUCAIM_MALE, endpoint_type)
+            );
+        }
+        assert!(
+            explain_eucaim(EUCAIM_MALE_NESTED, EndpointType::EucaimApi).contains("?gender=male\"")
+        );
+    }
+
+    // Lens nesting with a multi-value OR group, which normalizing would flatten
+    const OMOP_OR_GROUPS: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"AND","children":[{"operand":"OR","children":[{"key":"a","type":"EQUALS","value":"1"},{"key":"a","type":"EQUALS","value":"2"}]},{"operand":"OR","children":[{"key":"b","type":"EQUALS","value":"1"}]}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_explain_omop_as_is() {
+        let Explanation::Ast { ast } = explain(
+            serde_json::from_str(OMOP_OR_GROUPS).unwrap(),
+            "bbmri",
+            None,
+            EndpointType::Omop,
+            url(),
+        )
+        .unwrap() else {
+            panic!("OMOP queries are explained as the AST");
+        };
+        pretty_assertions::assert_eq!(
+            serde_json::to_value(ast).unwrap(),
+            serde_json::from_str::<Value>(OMOP_OR_GROUPS).unwrap()
+        );
+    }
+}
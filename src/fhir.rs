@@ -0,0 +1,507 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info, warn};
+
+use crate::blaze;
+use crate::config::CONFIG;
+use crate::errors::FocusError;
+use crate::measure_period::MeasurePeriod;
+
+/// Tag on the Libraries and Measures Focus posts, so they can be told apart from other resources when cleaning up
+pub const FOCUS_TAG_SYSTEM: &str = "https://github.com/samply/focus";
+pub const FOCUS_TAG_CODE: &str = "focus";
+
+/// How often the Libraries and Measures older than the configured age are deleted
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A FHIR server evaluating the Measures of CQL queries
+pub trait FhirBackend {
+    async fn check_availability(&self) -> bool;
+
+    /// Creates the Library unless one with the canonical URL exists, in a single conditional create
+    async fn post_library(&self, library: String, url: &str) -> Result<(), FocusError>;
+
+    /// Creates the Measure unless one with the canonical URL exists, in a single conditional create
+    async fn post_measure(&self, measure: String, url: &str) -> Result<(), FocusError>;
+
+    /// Evaluates the Measure with the canonical URL and returns the MeasureReport,
+    /// or [`FocusError::MeasureNotFound`] if the server doesn't have the Measure or its Library
+    async fn evaluate_measure(
+        &self,
+        url: String,
+        period: &MeasurePeriod,
+    ) -> Result<String, FocusError>;
+}
+
+/// Blaze, which evaluates Measures with GET requests
+pub struct Blaze;
+
+impl FhirBackend for Blaze {
+    async fn check_availability(&self) -> bool {
+        blaze::check_availability().await
+    }
+
+    async fn post_library(&self, library: String, url: &str) -> Result<(), FocusError> {
+        blaze::post_library(library, url).await
+    }
+
+    async fn post_measure(&self, measure: String, url: &str) -> Result<(), FocusError> {
+        blaze::post_measure(measure, url).await
+    }
+
+    async fn evaluate_measure(
+        &self,
+        url: String,
+        period: &MeasurePeriod,
+    ) -> Result<String, FocusError> {
+        blaze::evaluate_measure(url, period).await
+    }
+}
+
+/// Any FHIR R4 server implementing the `$evaluate-measure` and `$cql` operations of the Clinical Reasoning module, e.g. HAPI FHIR
+pub struct GenericFhir;
+
+impl GenericFhir {
+    /// Evaluates a CQL expression with the `$cql` operation and returns the resulting Parameters,
+    /// only used to check availability as queries run as Measures
+    pub async fn evaluate_cql(&self, expression: &str) -> Result<Value, FocusError> {
+        let parameters = json!({
+            "resourceType": "Parameters",
+            "parameter": [{"name": "expression", "valueString": expression}]
+        });
+        let resp = CONFIG
+            .client
+            .post(format!("{}$cql", CONFIG.endpoint_url))
+            .header("Content-Type", "application/fhir+json")
+            .body(parameters.to_string())
+            .send()
+            .await
+            .map_err(FocusError::MeasureEvaluationErrorReqwest)?;
+
+        if !resp.status().is_success() {
+            return Err(FocusError::MeasureEvaluationErrorFhir(format!(
+                "Error while evaluating CQL: {}",
+                resp.status()
+            )));
+        }
+        resp.json()
+            .await
+            .map_err(FocusError::MeasureEvaluationErrorReqwest)
+    }
+
+    async fn post_resource(
+        &self,
+        resource_type: &str,
+        resource: String,
+        url: &str,
+    ) -> Result<(), reqwest::Error> {
+        debug!("Creating a {}...", resource_type);
+        let resp = CONFIG
+            .client
+            .post(format!("{}{}", CONFIG.endpoint_url, resource_type))
+            .header("Content-Type", "application/fhir+json")
+            .header("If-None-Exist", format!("url={url}"))
+            .body(resource)
+            .send()
+            .await?;
+        if resp.error_for_status()?.status() == StatusCode::CREATED {
+            debug!("Successfully created a {}", resource_type);
+        } else {
+            debug!("Reusing the {} with canonical URL: {}", resource_type, url);
+        }
+        Ok(())
+    }
+}
+
+impl FhirBackend for GenericFhir {
+    /// The server is only usable if it evaluates CQL, so a trivial expression is evaluated
+    async fn check_availability(&self) -> bool {
+        debug!("Checking FHIR server availability...");
+        match self.evaluate_cql("1 + 1").await {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("FHIR server can't evaluate CQL: {}", e);
+                false
+            }
+        }
+    }
+
+    async fn post_library(&self, library: String, url: &str) -> Result<(), FocusError> {
+        self.post_resource("Library", library, url)
+            .await
+            .map_err(FocusError::UnableToPostLibrary)
+    }
+
+    async fn post_measure(&self, measure: String, url: &str) -> Result<(), FocusError> {
+        self.post_resource("Measure", measure, url)
+            .await
+            .map_err(FocusError::UnableToPostMeasure)
+    }
+
+    async fn evaluate_measure(
+        &self,
+        url: String,
+        period: &MeasurePeriod,
+    ) -> Result<String, FocusError> {
+        debug!(
+            "Evaluating the Measure with canonical URL {} for the period {}",
+            url, period
+        );
+        let resp = CONFIG
+            .client
+            .post(format!("{}Measure/$evaluate-measure", CONFIG.endpoint_url))
+            .header("Content-Type", "application/fhir+json")
+            .body(evaluate_measure_parameters(&url, period).to_string())
+            .send()
+            .await
+            .map_err(FocusError::MeasureEvaluationErrorReqwest)?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(FocusError::MeasureNotFound(url));
+        }
+        if resp.status() != StatusCode::OK {
+            warn!(
+                "Error while evaluating the Measure with canonical URL `{}`: {:?}",
+                url, resp
+            );
+            return Err(FocusError::MeasureEvaluationErrorFhir(format!(
+                "Error while evaluating the Measure with canonical URL `{}`: {}",
+                url,
+                resp.status()
+            )));
+        }
+        info!(
+            "Successfully evaluated the Measure with canonical URL: {}",
+            url
+        );
+        let report: Value = resp
+            .json()
+            .await
+            .map_err(FocusError::MeasureEvaluationErrorReqwest)?;
+        Ok(measure_report(report)?.to_string())
+    }
+}
+
+/// Input of the `$evaluate-measure` operation for the population of the whole server
+fn evaluate_measure_parameters(url: &str, period: &MeasurePeriod) -> Value {
+    json!({
+        "resourceType": "Parameters",
+        "parameter": [
+            {"name": "measure", "valueString": url},
+            {"name": "periodStart", "valueDate": period.start},
+            {"name": "periodEnd", "valueDate": period.end},
+            {"name": "reportType", "valueString": "population"}
+        ]
+    })
+}
+
+/// Servers may answer `$evaluate-measure` with the MeasureReport itself or wrapped in Parameters
+fn measure_report(response: Value) -> Result<Value, FocusError> {
+    if response["resourceType"] != "Parameters" {
+        return Ok(response);
+    }
+    response["parameter"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|parameter| parameter["resource"]["resourceType"] == "MeasureReport")
+        .map(|parameter| parameter["resource"].clone())
+        .ok_or_else(|| {
+            FocusError::MeasureEvaluationErrorFhir(
+                "No MeasureReport in the Parameters returned".into(),
+            )
+        })
+}
+
+/// Deletes the Measures and Libraries Focus created that were not updated for longer than the maximum age,
+/// found by their tag with the standard FHIR search that Blaze and other FHIR servers support alike
+async fn delete_old_resources(max_age: chrono::Duration) -> Result<(), FocusError> {
+    let cutoff = (Utc::now() - max_age).to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    // Measures first, as they reference the Libraries
+    for resource_type in ["Measure", "Library"] {
+        let deleted = delete_resources_before(resource_type, &cutoff).await?;
+        if deleted > 0 {
+            info!("Deleted {deleted} {resource_type} resources last updated before {cutoff}");
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the resources of the type Focus created that were last updated before the cutoff
+async fn delete_resources_before(resource_type: &str, cutoff: &str) -> Result<usize, FocusError> {
+    let mut deleted = 0;
+    loop {
+        let resp = CONFIG
+            .client
+            .get(format!("{}{}", CONFIG.endpoint_url, resource_type))
+            .query(&[
+                ("_tag", format!("{FOCUS_TAG_SYSTEM}|{FOCUS_TAG_CODE}")),
+                ("_lastUpdated", format!("lt{cutoff}")),
+                ("_elements", "id".into()),
+                ("_count", "100".into()),
+            ])
+            .send()
+            .await
+            .map_err(FocusError::UnableToSearchResources)?;
+        let bundle: Value = resp
+            .error_for_status()
+            .map_err(FocusError::UnableToSearchResources)?
+            .json()
+            .await
+            .map_err(FocusError::UnableToSearchResources)?;
+
+        let ids: Vec<&str> = bundle["entry"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry["resource"]["id"].as_str())
+            .collect();
+        if ids.is_empty() {
+            return Ok(deleted);
+        }
+        for id in ids {
+            let resp = CONFIG
+                .client
+                .delete(format!("{}{}/{}", CONFIG.endpoint_url, resource_type, id))
+                .send()
+                .await
+                .map_err(|e| FocusError::UnableToDeleteResources(e.to_string()))?;
+            if !resp.status().is_success() {
+                return Err(FocusError::UnableToDeleteResources(format!(
+                    "{resource_type}/{id}: {}",
+                    resp.status()
+                )));
+            }
+            deleted += 1;
+        }
+    }
+}
+
+/// Periodically deletes the Measures and Libraries Focus created that are older than the maximum age in days
+pub async fn clean_up_old_resources(max_age_days: u32) {
+    let max_age = chrono::Duration::days(max_age_days.into());
+    loop {
+        if let Err(e) = delete_old_resources(max_age).await {
+            error!("Failed to delete old resources from the FHIR server: {e}");
+        }
+        tokio::time::sleep(CLEANUP_INTERVAL).await;
+    }
+}
+
+/// Hash of the resource content without the fields that differ between otherwise equal resources
+fn content_hash(resource: &Value) -> String {
+    let mut resource = resource.clone();
+    if let Some(object) = resource.as_object_mut() {
+        for field in ["id", "url", "meta"] {
+            object.remove(field);
+        }
+    }
+    format!("{:x}", Sha256::digest(resource.to_string()))
+}
+
+/// Hash of what a Measure computes from its Library, i.e. its groups with their populations and stratifiers
+pub fn measure_groups_hash(measure: &Value) -> String {
+    format!("{:x}", Sha256::digest(measure["group"].to_string()))
+}
+
+/// Replaces the random canonical URLs of the Library and Measure by ones derived from their content,
+/// so that equal queries evaluate the same resources, and tags both as created by Focus
+fn with_content_urls(library: &Value, measure: &Value) -> Result<(Value, Value), FocusError> {
+    if !library.is_object() || !measure.is_object() {
+        return Err(FocusError::CQLQueryError);
+    }
+    let mut library = library.clone();
+    let mut measure = measure.clone();
+
+    let library_url = format!("urn:focus:library:{}", content_hash(&library));
+    library["url"] = json!(library_url);
+    measure["library"] = match measure["library"] {
+        Value::String(_) => json!(library_url),
+        _ => json!([library_url]),
+    };
+    measure["url"] = json!(format!("urn:focus:measure:{}", content_hash(&measure)));
+
+    for resource in [&mut library, &mut measure] {
+        resource["meta"] = json!({"tag": [{"system": FOCUS_TAG_SYSTEM, "code": FOCUS_TAG_CODE}]});
+    }
+    Ok((library, measure))
+}
+
+/// Evaluates the Measure of a CQL query, creating the Library and Measure only if the backend doesn't have them yet
+pub async fn run_cql_query(
+    backend: &impl FhirBackend,
+    library: &Value,
+    measure: &Value,
+    period: &MeasurePeriod,
+) -> Result<String, FocusError> {
+    let (library, measure) = with_content_urls(library, measure)?;
+    let (Some(library_url), Some(url)) = (library["url"].as_str(), measure["url"].as_str()) else {
+        return Err(FocusError::CQLQueryError);
+    };
+    debug!("Evaluating the Measure with canonical URL: {}", url);
+
+    let create = || async {
+        backend
+            .post_library(library.to_string(), library_url)
+            .await?;
+        backend.post_measure(measure.to_string(), url).await
+    };
+
+    create().await?;
+    match backend.evaluate_measure(url.to_string(), period).await {
+        // the cleanup only sees when resources were created, so it may delete them right after they were reused
+        Err(FocusError::MeasureNotFound(_)) => {
+            debug!(
+                "The Measure with canonical URL {} is gone, creating it again",
+                url
+            );
+            create().await?;
+            backend.evaluate_measure(url.to_string(), period).await
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn resources(library_url: &str, measure_url: &str, cql: &str) -> (Value, Value) {
+        (
+            json!({"resourceType": "Library", "url": library_url, "content": [{"data": cql}]}),
+            json!({"resourceType": "Measure", "url": measure_url, "library": library_url}),
+        )
+    }
+
+    #[test]
+    fn test_with_content_urls() {
+        let (library, measure) = resources("urn:uuid:1", "urn:uuid:2", "Q1FMIGE=");
+        let (library, measure) = with_content_urls(&library, &measure).unwrap();
+        let (other_library, other_measure) = resources("urn:uuid:3", "urn:uuid:4", "Q1FMIGE=");
+        let (other_library, other_measure) =
+            with_content_urls(&other_library, &other_measure).unwrap();
+
+        pretty_assertions::assert_eq!(library, other_library);
+        pretty_assertions::assert_eq!(measure, other_measure);
+        assert!(library["url"]
+            .as_str()
+            .unwrap()
+            .starts_with("urn:focus:library:"));
+        assert!(measure["url"]
+            .as_str()
+            .unwrap()
+            .starts_with("urn:focus:measure:"));
+        pretty_assertions::assert_eq!(measure["library"], library["url"]);
+        pretty_assertions::assert_eq!(measure["meta"]["tag"][0]["code"], FOCUS_TAG_CODE);
+
+        let (changed_library, changed_measure) = resources("urn:uuid:1", "urn:uuid:2", "Q1FMIGI=");
+        let (changed_library, changed_measure) =
+            with_content_urls(&changed_library, &changed_measure).unwrap();
+        assert_ne!(library["url"], changed_library["url"]);
+        assert_ne!(measure["url"], changed_measure["url"]);
+
+        assert!(with_content_urls(&json!("library"), &measure).is_err());
+    }
+
+    #[test]
+    fn test_measure_groups_hash() {
+        let measure = |library_url: &str, stratifiers: Value| json!({"resourceType": "Measure", "library": library_url, "group": [{"stratifier": stratifiers}]});
+        let gender = json!([{"code": {"text": "Gender"}}]);
+        let age = json!([{"code": {"text": "Age"}}]);
+
+        pretty_assertions::assert_eq!(
+            measure_groups_hash(&measure("urn:uuid:1", gender.clone())),
+            measure_groups_hash(&measure("urn:uuid:2", gender.clone()))
+        );
+        assert_ne!(
+            measure_groups_hash(&measure("urn:uuid:1", gender)),
+            measure_groups_hash(&measure("urn:uuid:1", age))
+        );
+    }
+
+    /// Backend that deleted its resources once, right after they were created
+    #[derive(Default)]
+    struct CleanedUpOnce {
+        posted: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl FhirBackend for CleanedUpOnce {
+        async fn check_availability(&self) -> bool {
+            true
+        }
+
+        async fn post_library(&self, _library: String, url: &str) -> Result<(), FocusError> {
+            self.posted.lock().unwrap().push(url.to_string());
+            Ok(())
+        }
+
+        async fn post_measure(&self, _measure: String, url: &str) -> Result<(), FocusError> {
+            self.posted.lock().unwrap().push(url.to_string());
+            Ok(())
+        }
+
+        async fn evaluate_measure(
+            &self,
+            url: String,
+            _period: &MeasurePeriod,
+        ) -> Result<String, FocusError> {
+            match self.posted.lock().unwrap().len() {
+                2 => Err(FocusError::MeasureNotFound(url)),
+                _ => Ok("report".into()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_cql_query_recreates_deleted_resources() {
+        let backend = CleanedUpOnce::default();
+        let (library, measure) = resources("urn:uuid:1", "urn:uuid:2", "cql");
+        let period = MeasurePeriod {
+            start: "2000".into(),
+            end: "2030".into(),
+        };
+
+        pretty_assertions::assert_eq!(
+            run_cql_query(&backend, &library, &measure, &period)
+                .await
+                .unwrap(),
+            "report"
+        );
+        pretty_assertions::assert_eq!(backend.posted.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_measure_report() {
+        let report = json!({"resourceType": "MeasureReport", "status": "complete"});
+        pretty_assertions::assert_eq!(measure_report(report.clone()).unwrap(), report);
+
+        let wrapped = json!({
+            "resourceType": "Parameters",
+            "parameter": [{"name": "return", "resource": report}]
+        });
+        pretty_assertions::assert_eq!(measure_report(wrapped).unwrap(), report);
+
+        let empty = json!({"resourceType": "Parameters", "parameter": []});
+        assert!(measure_report(empty).is_err());
+    }
+
+    #[test]
+    fn test_evaluate_measure_parameters() {
+        let period = MeasurePeriod {
+            start: "1900".into(),
+            end: "2030-06".into(),
+        };
+        let parameters = evaluate_measure_parameters("urn:focus:measure:1", &period);
+
+        pretty_assertions::assert_eq!(
+            parameters["parameter"][0]["valueString"],
+            "urn:focus:measure:1"
+        );
+        pretty_assertions::assert_eq!(parameters["parameter"][1]["valueDate"], "1900");
+        pretty_assertions::assert_eq!(parameters["parameter"][2]["valueDate"], "2030-06");
+    }
+}
@@ -9,6 +9,7 @@ use tracing::{debug, warn};
 use crate::ast;
 use crate::config::CONFIG;
 use crate::errors::FocusError;
+use crate::validation;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct IntermediateRepQuery {
@@ -16,6 +17,16 @@ pub struct IntermediateRepQuery {
     pub query: String,
 }
 
+/// The AST as Focus builds EUCAIM queries from it: validated, normalized, and nested like Lens does, as the query builders expect.
+/// OMOP endpoints build their queries themselves and get the AST as is
+pub fn prepare(ast: ast::Ast) -> Result<ast::Ast, FocusError> {
+    let ast = validation::validated(ast, None)?;
+    Ok(ast::Ast {
+        ast: ast.ast.lens_nesting(),
+        id: ast.id,
+    })
+}
+
 pub async fn post_ast(ast: ast::Ast) -> Result<String, FocusError> {
     debug!("Posting AST...");
 
@@ -4,18 +4,27 @@ mod beam;
 mod blaze;
 mod config;
 mod cql;
+mod cql_syntax;
 mod errors;
+mod explain;
+mod fhir;
 mod graceful_shutdown;
 mod logger;
+mod measure_period;
 
 mod eucaim_api;
 mod exporter;
 mod intermediate_rep;
 mod mr;
+mod obfuscation;
 mod projects;
 mod task_processing;
+mod terminology;
+mod tools;
 mod transformed;
+mod units;
 mod util;
+mod validation;
 
 #[cfg(feature = "query-sql")]
 mod db;
@@ -31,17 +40,18 @@ use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use beam_lib::{TaskRequest, TaskResult};
 use futures_util::future::BoxFuture;
 use futures_util::FutureExt;
-use laplace_rs::ObfCache;
 use tokio::sync::Mutex;
 
 use crate::blaze::parse_blaze_query_payload_ast;
 use crate::config::EndpointType;
-use crate::util::{base64_decode, is_cql_tampered_with, obfuscate_counts_mr};
+use crate::fhir::{FhirBackend, GenericFhir};
+use crate::measure_period::MeasurePeriod;
+use crate::obfuscation::{ObfCaches, ObfuscationScope};
+use crate::util::{base64_decode, check_cql_tampering, obfuscate_counts_mr};
 use crate::{config::CONFIG, errors::FocusError};
 use blaze::{CqlQuery, Language};
 
 use std::collections::{HashMap, HashSet};
-use std::ops::DerefMut;
 use std::process::ExitCode;
 use std::str;
 use std::sync::Arc;
@@ -49,11 +59,13 @@ use std::time::Instant;
 use std::{process::exit, time::Duration};
 
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 
 // result cache
 type SearchQuery = String;
-type Obfuscated = bool;
+type Obfuscated = Option<ObfuscationScope>;
+/// How a CQL query is evaluated: a hash of the Measure groups, as the stratifiers computed for the same query can differ, and the period
+type Evaluation = Option<(String, MeasurePeriod)>;
 type QueryResult = String;
 type BeamTask = TaskRequest<String>;
 type BeamResult = TaskResult<beam_lib::RawString>;
@@ -72,12 +84,21 @@ struct Metadata {
     task_type: Option<exporter::TaskType>,
     #[serde(default)]
     transform: Transform,
+    /// Answer with the generated query instead of running it, only for requesters in `explain_requesters`
+    #[serde(default)]
+    explain: bool,
+    /// Stratifiers to compute for queries generated from ASTs, all of the project if not given
+    #[serde(default)]
+    stratifiers: Option<Vec<String>>,
+    /// Period to evaluate the Measure for instead of the configured one, only for requesters in `measure_period_requesters`
+    #[serde(default)]
+    measure_period: Option<MeasurePeriod>,
 }
 
 #[derive(Debug, Clone, Default)]
 struct QueryResultCache {
     queries_to_cache: HashSet<String>,
-    cache: HashMap<(SearchQuery, Obfuscated, Transform), (QueryResult, Instant)>,
+    cache: HashMap<(SearchQuery, Obfuscated, Transform, Evaluation), (QueryResult, Instant)>,
 }
 
 impl QueryResultCache {
@@ -111,12 +132,19 @@ impl QueryResultCache {
         }
     }
 
-    pub fn insert(&mut self, key: (SearchQuery, Obfuscated, Transform), value: QueryResult) {
+    pub fn insert(
+        &mut self,
+        key: (SearchQuery, Obfuscated, Transform, Evaluation),
+        value: QueryResult,
+    ) {
         let created = Instant::now();
         self.cache.insert(key, (value, created));
     }
 
-    pub fn get(&self, key: &(SearchQuery, Obfuscated, Transform)) -> QueryResultCacheOutcome {
+    pub fn get(
+        &self,
+        key: &(SearchQuery, Obfuscated, Transform, Evaluation),
+    ) -> QueryResultCacheOutcome {
         if !self.queries_to_cache.contains(&key.0) {
             return QueryResultCacheOutcome::DontCache;
         }
@@ -171,6 +199,10 @@ struct TotalCount {
 
 #[tokio::main]
 pub async fn main() -> ExitCode {
+    if let Some(code) = tools::run_if_requested() {
+        return code;
+    }
+
     if let Err(e) = logger::init_logger() {
         error!("Cannot initalize logger: {}", e);
         exit(1);
@@ -181,6 +213,44 @@ pub async fn main() -> ExitCode {
 
     let _ = CONFIG.api_key; // Initialize config
 
+    if let Some(dir) = &CONFIG.projects_dir {
+        match projects::load_projects(dir) {
+            Ok(names) => info!(
+                "Loaded projects {} from {}",
+                names.join(", "),
+                dir.display()
+            ),
+            Err(e) => {
+                error!("Cannot load projects: {}", e);
+                exit(1);
+            }
+        }
+    }
+    match projects::check_projects(projects::names().iter().map(String::as_str)) {
+        Ok(problems) => {
+            for problem in problems {
+                warn!("Problem in project definition {}", problem);
+            }
+        }
+        Err(e) => {
+            error!("Cannot check projects: {}", e);
+            exit(1);
+        }
+    }
+    if let Some(dir) = &CONFIG.terminology_dir {
+        match terminology::load_hierarchies(dir) {
+            Ok(systems) => info!(
+                "Loaded hierarchies of {} from {}",
+                systems.join(", "),
+                dir.display()
+            ),
+            Err(e) => {
+                error!("Cannot load terminology: {}", e);
+                exit(1);
+            }
+        }
+    }
+
     tokio::select! {
         _ = graceful_shutdown::wait_for_signal() => {
             ExitCode::SUCCESS
@@ -204,8 +274,6 @@ async fn get_db_pool() -> Result<Option<DbPool>, ExitCode> {
 
 #[cfg(feature = "query-sql")]
 async fn get_db_pool() -> Result<Option<DbPool>, ExitCode> {
-    use tracing::info;
-
     if let Some(connection_string) = CONFIG.postgres_connection_string.clone() {
         match db::get_pg_connection_pool(&connection_string, CONFIG.max_db_attempts).await {
             Err(e) => {
@@ -232,6 +300,7 @@ async fn main_loop() -> ExitCode {
     };
     let endpoint_service_available: fn() -> BoxFuture<'static, bool> = match CONFIG.endpoint_type {
         EndpointType::Blaze => || blaze::check_availability().boxed(),
+        EndpointType::Fhir => || async { GenericFhir.check_availability().await }.boxed(),
         EndpointType::Omop | EndpointType::EucaimApi => || async { true }.boxed(), // TODO health check
         #[cfg(feature = "query-sql")]
         EndpointType::EucaimSql => || async { true }.boxed(),
@@ -256,10 +325,20 @@ async fn main_loop() -> ExitCode {
             failures, CONFIG.retry_count
         );
     }
+    if let Some(max_age_days) = CONFIG.resource_max_age_days {
+        match CONFIG.endpoint_type {
+            EndpointType::Blaze | EndpointType::Fhir => {
+                tokio::spawn(fhir::clean_up_old_resources(max_age_days));
+            }
+            #[cfg(feature = "query-sql")]
+            EndpointType::BlazeAndSql => {
+                tokio::spawn(fhir::clean_up_old_resources(max_age_days));
+            }
+            _ => warn!("Resource max age is set, but the endpoint is not a FHIR server"),
+        }
+    }
     let query_result_cache = Arc::new(Mutex::new(QueryResultCache::new()));
-    let obf_cache = Arc::new(Mutex::new(ObfCache {
-        cache: Default::default(),
-    }));
+    let obf_cache = Arc::new(Mutex::new(ObfCaches::default()));
     task_processing::process_tasks(move |task| {
         let obf_cache = obf_cache.clone();
         let query_result_cache = query_result_cache.clone();
@@ -271,7 +350,7 @@ async fn main_loop() -> ExitCode {
 
 async fn process_task(
     task: &BeamTask,
-    obf_cache: Arc<Mutex<ObfCache>>,
+    obf_cache: Arc<Mutex<ObfCaches>>,
     query_result_cache: Arc<Mutex<QueryResultCache>>,
     db_pool: Option<DbPool>,
 ) -> Result<BeamResult, FocusError> {
@@ -283,6 +362,9 @@ async fn process_task(
         project: "default_obfuscation".to_string(),
         task_type: None,
         transform: Transform::None,
+        explain: false,
+        stratifiers: None,
+        measure_period: None,
     });
 
     debug!("{:?}", &metadata);
@@ -302,9 +384,12 @@ async fn process_task(
         let mut body = task.body.clone();
         return run_exporter_query(task, &mut body, task_type).await;
     }
+    if metadata.explain {
+        return explain_task(task, &metadata);
+    }
 
     match CONFIG.endpoint_type {
-        EndpointType::Blaze => {
+        EndpointType::Blaze | EndpointType::Fhir => {
             let mut generated_from_ast: bool = false;
             let data = base64_decode(&task.body)?;
             let query: CqlQuery = match serde_json::from_slice::<Language>(&data)? {
@@ -324,6 +409,7 @@ async fn process_task(
                     serde_json::from_str(&cql::generate_body(
                         parse_blaze_query_payload_ast(&ast_query.payload)?,
                         metadata.project.parse()?,
+                        metadata.stratifiers.as_deref(),
                     )?)?
                 }
             };
@@ -332,8 +418,7 @@ async fn process_task(
                 &query,
                 obf_cache,
                 query_result_cache,
-                metadata.project,
-                metadata.transform,
+                &metadata,
                 generated_from_ast,
             )
             .await
@@ -361,6 +446,7 @@ async fn process_task(
                         serde_json::from_str(&cql::generate_body(
                             parse_blaze_query_payload_ast(&ast_query.payload)?,
                             metadata.project.parse()?,
+                            metadata.stratifiers.as_deref(),
                         )?)?
                     }
                 };
@@ -369,8 +455,7 @@ async fn process_task(
                     &query,
                     obf_cache,
                     query_result_cache,
-                    metadata.project,
-                    metadata.transform,
+                    &metadata,
                     generated_from_ast,
                 )
                 .await
@@ -415,68 +500,138 @@ async fn process_task(
             }
         }
         EndpointType::Omop => {
-            let decoded = util::base64_decode(&task.body)?;
-            let intermediate_rep_query: intermediate_rep::IntermediateRepQuery =
-                serde_json::from_slice(&decoded)?;
             //TODO check that the language is ast
-            let query_decoded = general_purpose::STANDARD
-                .decode(intermediate_rep_query.query)
-                .map_err(FocusError::DecodeError)?;
-            let ast: ast::Ast = serde_json::from_slice(&query_decoded)?;
+            let ast = parse_intermediate_rep_ast(&task.body)?;
+            // the query mediator builds the query, so it gets the AST as is
+            validation::ensure_valid(&ast, None)?;
 
             Ok(run_intermediate_rep_query(task, ast).await?)
         }
         EndpointType::EucaimApi => {
-            let decoded = util::base64_decode(&task.body)?;
-            let intermediate_rep_query: intermediate_rep::IntermediateRepQuery =
-                serde_json::from_slice(&decoded)?;
             //TODO check that the language is ast
-            let query_decoded = general_purpose::STANDARD
-                .decode(intermediate_rep_query.query)
-                .map_err(FocusError::DecodeError)?;
-            let ast: ast::Ast = serde_json::from_slice(&query_decoded)?;
+            let ast = intermediate_rep::prepare(parse_intermediate_rep_ast(&task.body)?)?;
 
             Ok(run_eucaim_api_query(task, ast).await?)
         }
         #[cfg(feature = "query-sql")]
         EndpointType::EucaimSql => {
-            let decoded = util::base64_decode(&task.body)?;
-            let intermediate_rep_query: intermediate_rep::IntermediateRepQuery =
-                serde_json::from_slice(&decoded)?;
             //TODO check that the language is ast
-            let query_decoded = general_purpose::STANDARD
-                .decode(intermediate_rep_query.query)
-                .map_err(FocusError::DecodeError)?;
-            let ast: ast::Ast = serde_json::from_slice(&query_decoded)?;
+            let ast = intermediate_rep::prepare(parse_intermediate_rep_ast(&task.body)?)?;
 
             let sql_query_maybe = eucaim_sql::build_eucaim_sql_query(ast);
-            if let Ok(sql_query) = sql_query_maybe {
-                if let Some(pool) = db_pool {
-                    run_eucaim_sql_query(task, pool, sql_query, query_result_cache).await
-                } else {
-                    Err(FocusError::CannotConnectToDatabase(
-                        "SQL task but no connection String in config".into(),
+            match sql_query_maybe {
+                Ok(sql_query) => {
+                    if let Some(pool) = db_pool {
+                        run_eucaim_sql_query(task, pool, sql_query, query_result_cache).await
+                    } else {
+                        Err(FocusError::CannotConnectToDatabase(
+                            "SQL task but no connection String in config".into(),
+                        ))
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Wrong type of query for an SQL only store: {}, {:?}: {}",
+                        CONFIG.endpoint_type, task.body, e
+                    );
+                    Ok(beam::beam_result::perm_failed(
+                        CONFIG.beam_app_id_long.clone(),
+                        vec![task.from.clone()],
+                        task.id,
+                        format!(
+                            "Wrong type of query for an SQL only store: {}, {:?}: {}",
+                            CONFIG.endpoint_type, task.body, e
+                        ),
                     ))
                 }
-            } else {
-                warn!(
-                    "Wrong type of query for an SQL only store: {}, {:?}",
-                    CONFIG.endpoint_type, decoded
-                );
-                Ok(beam::beam_result::perm_failed(
-                    CONFIG.beam_app_id_long.clone(),
-                    vec![task.from.clone()],
-                    task.id,
-                    format!(
-                        "Wrong type of query for an SQL only store: {}, {:?}",
-                        CONFIG.endpoint_type, decoded
-                    ),
-                ))
             }
         }
     }
 }
 
+/// Checks the evaluation period requested in the task metadata, which only allowed requesters may set
+fn requested_measure_period(
+    task: &BeamTask,
+    measure_period: Option<MeasurePeriod>,
+) -> Result<Option<MeasurePeriod>, FocusError> {
+    let Some(measure_period) = measure_period else {
+        return Ok(None);
+    };
+    let requester = task.from.to_string();
+    if !CONFIG
+        .measure_period_requesters
+        .as_ref()
+        .is_some_and(|requesters| requesters.contains(&requester))
+    {
+        return Err(FocusError::MeasurePeriodNotAllowed(requester));
+    }
+    measure_period.validate()?;
+    Ok(Some(measure_period))
+}
+
+/// Answers with the query that would be run for the task instead of its result, so allowed requesters can debug queries
+fn explain_task(task: &BeamTask, metadata: &Metadata) -> Result<BeamResult, FocusError> {
+    let requester = task.from.to_string();
+    if !CONFIG
+        .explain_requesters
+        .as_ref()
+        .is_some_and(|requesters| requesters.contains(&requester))
+    {
+        return Err(FocusError::ExplainNotAllowed(requester));
+    }
+
+    let explain_ast = |ast: ast::Ast| {
+        explain::explain(
+            ast,
+            &metadata.project,
+            metadata.stratifiers.as_deref(),
+            CONFIG.endpoint_type,
+            CONFIG.endpoint_url.clone(),
+        )
+    };
+    let blaze_explanation = || -> Result<explain::Explanation, FocusError> {
+        match serde_json::from_slice::<Language>(&base64_decode(&task.body)?)? {
+            Language::Cql(cql_query) => explain::Explanation::from_cql_query(cql_query),
+            Language::Ast(ast_query) => {
+                explain_ast(parse_blaze_query_payload_ast(&ast_query.payload)?)
+            }
+        }
+    };
+    let explanation = match CONFIG.endpoint_type {
+        EndpointType::Blaze | EndpointType::Fhir => blaze_explanation()?,
+        #[cfg(feature = "query-sql")]
+        EndpointType::BlazeAndSql => blaze_explanation()?,
+        EndpointType::Omop | EndpointType::EucaimApi => {
+            explain_ast(parse_intermediate_rep_ast(&task.body)?)?
+        }
+        #[cfg(feature = "query-sql")]
+        EndpointType::EucaimSql => explain_ast(parse_intermediate_rep_ast(&task.body)?)?,
+        #[cfg(feature = "query-sql")]
+        EndpointType::Sql => {
+            return Err(FocusError::ExplainNotSupported(
+                CONFIG.endpoint_type.to_string(),
+            ))
+        }
+    };
+
+    Ok(beam::beam_result::succeeded(
+        CONFIG.beam_app_id_long.clone(),
+        vec![task.from.clone()],
+        task.id,
+        BASE64.encode(serde_json::to_string(&explanation)?),
+    ))
+}
+
+/// The AST of a task in the intermediate representation sent to OMOP and EUCAIM endpoints
+fn parse_intermediate_rep_ast(body: &str) -> Result<ast::Ast, FocusError> {
+    let intermediate_rep_query: intermediate_rep::IntermediateRepQuery =
+        serde_json::from_slice(&util::base64_decode(body)?)?;
+    let query_decoded = general_purpose::STANDARD
+        .decode(intermediate_rep_query.query)
+        .map_err(FocusError::DecodeError)?;
+    Ok(serde_json::from_slice(&query_decoded)?)
+}
+
 #[cfg(feature = "query-sql")]
 async fn run_eucaim_sql_query(
     task: &TaskRequest<String>,
@@ -488,7 +643,7 @@ async fn run_eucaim_sql_query(
         match query_result_cache
             .lock()
             .await
-            .get(&(sql_query.clone(), false, Transform::None))
+            .get(&(sql_query.clone(), None, Transform::None, None))
         {
             QueryResultCacheOutcome::Cached(result) => {
                 return Ok(beam::beam_result::succeeded(
@@ -545,10 +700,10 @@ async fn run_eucaim_sql_query(
         dbg!(&response_json);
 
         if should_cache {
-            query_result_cache
-                .lock()
-                .await
-                .insert((sql_query, false, Transform::None), response_json.clone());
+            query_result_cache.lock().await.insert(
+                (sql_query, None, Transform::None, None),
+                response_json.clone(),
+            );
         }
 
         Ok(beam::beam_result::succeeded(
@@ -573,8 +728,9 @@ async fn run_sql_key_query(
 ) -> Result<TaskResult<beam_lib::RawString>, FocusError> {
     let should_cache = match query_result_cache.lock().await.get(&(
         sql_query.payload.clone(),
-        false,
+        None,
         Transform::None,
+        None,
     )) {
         QueryResultCacheOutcome::Cached(result) => {
             return Ok(beam::beam_result::succeeded(
@@ -593,7 +749,7 @@ async fn run_sql_key_query(
 
         if should_cache {
             query_result_cache.lock().await.insert(
-                (sql_query.payload, false, Transform::None),
+                (sql_query.payload, None, Transform::None, None),
                 rows_json.to_string(),
             );
         }
@@ -614,12 +770,14 @@ async fn run_sql_key_query(
 async fn run_cql_query(
     task: &BeamTask,
     query: &CqlQuery,
-    obf_cache: Arc<Mutex<ObfCache>>,
+    obf_cache: Arc<Mutex<ObfCaches>>,
     query_result_cache: Arc<Mutex<QueryResultCache>>,
-    project: String,
-    transform: Transform,
+    metadata: &Metadata,
     generated_from_ast: bool,
 ) -> Result<BeamResult, FocusError> {
+    let project = &metadata.project;
+    let transform = metadata.transform;
+    let measure_period = requested_measure_period(task, metadata.measure_period.clone())?;
     let encoded_query =
         query.lib["content"][0]["data"]
             .as_str()
@@ -628,13 +786,21 @@ async fn run_cql_query(
                 query.lib
             )))?;
 
-    let obfuscate =
-        CONFIG.obfuscate == config::Obfuscate::Yes && !CONFIG.unobfuscated.contains(&project);
+    let obfuscation_scope = (CONFIG.obfuscate == config::Obfuscate::Yes
+        && !CONFIG.unobfuscated.contains(project))
+    .then(|| CONFIG.obfuscation_scope(project));
+
+    let measure_period = measure_period.unwrap_or_else(|| CONFIG.measure_period(project).clone());
+    let evaluation = Some((
+        fhir::measure_groups_hash(&query.measure),
+        measure_period.clone(),
+    ));
 
     let should_cache = match query_result_cache.lock().await.get(&(
         encoded_query.to_string(),
-        obfuscate,
+        obfuscation_scope.clone(),
         transform,
+        evaluation.clone(),
     )) {
         QueryResultCacheOutcome::Cached(result) => {
             return Ok(beam::beam_result::succeeded(
@@ -657,26 +823,22 @@ async fn run_cql_query(
     trace!("Library: {}", &query.lib);
     trace!("Measure: {}", &query.measure);
 
-    let cql_result = blaze::run_cql_query(&query.lib, &query.measure).await?;
+    let cql_result = match CONFIG.endpoint_type {
+        EndpointType::Fhir => {
+            fhir::run_cql_query(&GenericFhir, &query.lib, &query.measure, &measure_period).await?
+        }
+        _ => fhir::run_cql_query(&fhir::Blaze, &query.lib, &query.measure, &measure_period).await?,
+    };
 
     trace!("MeasureReport with unobfuscated values: {}", &cql_result);
 
-    let cql_result_new: String = match obfuscate {
-        true => obfuscate_counts_mr(
+    let cql_result_new: String = match &obfuscation_scope {
+        Some(scope) => obfuscate_counts_mr(
             &cql_result,
-            obf_cache.lock().await.deref_mut(),
-            CONFIG.obfuscate_zero,
-            CONFIG.obfuscate_below_10_mode,
-            CONFIG.delta_patient,
-            CONFIG.delta_specimen,
-            CONFIG.delta_diagnosis,
-            CONFIG.delta_procedures,
-            CONFIG.delta_medication_statements,
-            CONFIG.delta_histo,
-            CONFIG.epsilon,
-            CONFIG.rounding_step,
+            obf_cache.lock().await.get_mut(scope),
+            CONFIG.obfuscation_params(scope),
         )?,
-        false => cql_result,
+        None => cql_result,
     };
 
     let result_string = match transform {
@@ -691,7 +853,12 @@ async fn run_cql_query(
 
     if should_cache {
         query_result_cache.lock().await.insert(
-            (encoded_query.to_string(), obfuscate, transform),
+            (
+                encoded_query.to_string(),
+                obfuscation_scope,
+                transform,
+                evaluation,
+            ),
             result_string.clone(),
         );
     }
@@ -822,17 +989,16 @@ fn replace_cql_library(mut query: CqlQuery) -> Result<CqlQuery, FocusError> {
     let decoded_string = str::from_utf8(&decoded_cql)
         .map_err(|_| FocusError::ParsingError("CQL query was invalid".into()))?;
 
-    match is_cql_tampered_with(decoded_string) {
-        false => debug!("CQL not tampered with"),
-        true => {
+    match check_cql_tampering(decoded_string) {
+        Ok(()) => debug!("CQL not tampered with"),
+        Err(e) => {
             debug!("CQL tampered with");
-            return Err(FocusError::CQLTemperedWithError(
-                "'define' keyword found in CQL".to_string(),
-            ));
+            return Err(e);
         }
     };
 
     let replaced_cql_str = util::replace_cql(decoded_string);
+    cql_syntax::ensure_valid(&replaced_cql_str)?;
     let replaced_cql_str_base64 = BASE64.encode(replaced_cql_str);
     let new_data_value = serde_json::to_value(replaced_cql_str_base64)
         .expect("unable to turn base64 string into json value - this should not happen");
@@ -866,9 +1032,13 @@ mod test {
             project: "default_obfuscation".to_string(),
             task_type: None,
             transform: Transform::None,
+            explain: false,
+            stratifiers: None,
+            measure_period: None,
         });
 
         assert_eq!(metadata.task_type, None);
+        assert!(!metadata.explain);
     }
 
     #[test]
@@ -877,4 +1047,28 @@ mod test {
 
         assert_eq!(metadata.task_type, Some(exporter::TaskType::Execute));
     }
+
+    #[test]
+    fn test_metadata_deserialization_explain() {
+        let metadata: Metadata =
+            serde_json::from_str(r#"{"project": "bbmri", "explain": true}"#).unwrap();
+
+        assert!(metadata.explain);
+    }
+
+    #[test]
+    fn test_metadata_deserialization_measure_period() {
+        let metadata: Metadata = serde_json::from_str(
+            r#"{"project": "dktk", "measure_period": {"start": "1900", "end": "2040-06"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata.measure_period,
+            Some(MeasurePeriod {
+                start: "1900".into(),
+                end: "2040-06".into()
+            })
+        );
+    }
 }
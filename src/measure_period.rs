@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::FocusError;
+
+/// Period Blaze evaluates Measures for, as FHIR dates, i.e. a year, a month or a day
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MeasurePeriod {
+    pub start: String,
+    pub end: String,
+}
+
+impl MeasurePeriod {
+    /// Checks that start and end are FHIR dates and the period does not end before it starts
+    pub fn validate(&self) -> Result<(), FocusError> {
+        let start = first_day(&self.start)?;
+        let end = first_day(&self.end)?;
+        // the end is a whole year or month if given without day, so only its first day has to be checked against the start
+        if end < start && !self.start.starts_with(&self.end) {
+            return Err(FocusError::InvalidMeasurePeriod(format!(
+                "{self} ends before it starts"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// First day of a FHIR date, which may be just a year or a month
+fn first_day(date: &str) -> Result<NaiveDate, FocusError> {
+    let full = match date.len() {
+        4 => format!("{date}-01-01"),
+        7 => format!("{date}-01"),
+        10 => date.to_string(),
+        _ => String::new(),
+    };
+    NaiveDate::parse_from_str(&full, "%Y-%m-%d").map_err(|_| {
+        FocusError::InvalidMeasurePeriod(format!(
+            "{date} is not a date like 2000, 2000-01 or 2000-01-01"
+        ))
+    })
+}
+
+impl fmt::Display for MeasurePeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.start, self.end)
+    }
+}
+
+/// Parses periods written like ISO 8601 intervals, e.g. 1900/2030
+impl FromStr for MeasurePeriod {
+    type Err = FocusError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((start, end)) = s.trim().split_once('/') else {
+            return Err(FocusError::InvalidMeasurePeriod(format!(
+                "{s} is not a period like 2000/2030"
+            )));
+        };
+        let period = MeasurePeriod {
+            start: start.into(),
+            end: end.into(),
+        };
+        period.validate()?;
+        Ok(period)
+    }
+}
+
+/// Parses the periods of projects, each written like dktk=1900/2030
+pub fn parse_project_periods(
+    periods: &[String],
+) -> Result<HashMap<String, MeasurePeriod>, FocusError> {
+    periods
+        .iter()
+        .map(|entry| {
+            let Some((project, period)) = entry.split_once('=') else {
+                return Err(FocusError::InvalidMeasurePeriod(format!(
+                    "{entry} is not a project period like dktk=1900/2030"
+                )));
+            };
+            Ok((project.trim().to_string(), period.parse()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn period(start: &str, end: &str) -> MeasurePeriod {
+        MeasurePeriod {
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(period("1900", "2100").validate().is_ok());
+        assert!(period("2000-03", "2000-03-01").validate().is_ok());
+        assert!(period("2000-03-15", "2000-03").validate().is_ok());
+        assert!(period("2000-03-15", "2000-03-14").validate().is_err());
+        assert!(period("2030", "2000").validate().is_err());
+        assert!(period("2000-13", "2030").validate().is_err());
+        assert!(period("2000", "30").validate().is_err());
+        assert!(period("2000", "2030-02-30").validate().is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        pretty_assertions::assert_eq!(
+            "1900/2030".parse::<MeasurePeriod>().unwrap(),
+            period("1900", "2030")
+        );
+        assert!("1900-2030".parse::<MeasurePeriod>().is_err());
+
+        let periods = parse_project_periods(&[
+            "dktk=1900/2030".to_string(),
+            "exliquid=2010-06/2040".to_string(),
+        ])
+        .unwrap();
+        pretty_assertions::assert_eq!(periods["dktk"], period("1900", "2030"));
+        pretty_assertions::assert_eq!(periods["exliquid"], period("2010-06", "2040"));
+        assert!(parse_project_periods(&["dktk:1900/2030".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use laplace_rs::{ObfCache, ObfuscateBelow10Mode};
+use serde::Deserialize;
+
+use crate::errors::FocusError;
+
+/// The mode of obfuscating values below 10
+#[derive(clap::ValueEnum, Deserialize, Clone, PartialEq, Debug, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Below10Mode {
+    /// Return zero
+    #[value(alias = "0")]
+    Zero,
+    /// Return ten
+    #[value(alias = "1")]
+    Ten,
+    /// Obfuscate using Laplace distribution and rounding
+    #[value(alias = "2")]
+    Obfuscate,
+}
+
+impl From<Below10Mode> for ObfuscateBelow10Mode {
+    fn from(mode: Below10Mode) -> Self {
+        match mode {
+            Below10Mode::Zero => ObfuscateBelow10Mode::Zero,
+            Below10Mode::Ten => ObfuscateBelow10Mode::Ten,
+            Below10Mode::Obfuscate => ObfuscateBelow10Mode::Obfuscate,
+        }
+    }
+}
+
+/// Named sets of obfuscation parameters, individually set parameters take precedence
+#[derive(clap::ValueEnum, Deserialize, Clone, PartialEq, Debug, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ObfuscationPreset {
+    /// The parameters Focus has always been using
+    Default,
+    /// Halved privacy budget, coarser rounding and obfuscated zero counts
+    Strict,
+}
+
+impl ObfuscationPreset {
+    pub fn params(self) -> ObfuscationParams {
+        let default = ObfuscationParams {
+            obfuscate_zero: false,
+            below_10_mode: Below10Mode::Ten,
+            delta_patient: 1.,
+            delta_specimen: 20.,
+            delta_diagnosis: 3.,
+            delta_procedures: 1.7,
+            delta_medication_statements: 2.1,
+            delta_histo: 20.,
+            epsilon: 0.28,
+            rounding_step: 10,
+        };
+        match self {
+            ObfuscationPreset::Default => default,
+            ObfuscationPreset::Strict => ObfuscationParams {
+                obfuscate_zero: true,
+                epsilon: 0.14,
+                rounding_step: 20,
+                ..default
+            },
+        }
+    }
+}
+
+/// Obfuscation parameters as given on the command line or in the environment
+#[derive(clap::Args, Debug, Clone)]
+pub struct ObfuscationArgs {
+    /// Named set of obfuscation parameters the parameters below default to
+    #[clap(long, env, value_parser = clap::value_parser!(ObfuscationPreset), default_value = "default")]
+    obfuscation_preset: ObfuscationPreset,
+
+    /// Should zero values be obfuscated, "true" or "false"; preset default: false, true for "strict"
+    #[clap(long, env, value_parser = clap::builder::BoolishValueParser::new(), num_args = 0..=1, default_missing_value = "true")]
+    obfuscate_zero: Option<bool>,
+
+    /// The mode of obfuscating values below 10: "zero" (or 0), "ten" (or 1), "obfuscate" (or 2); preset default: "ten"
+    #[clap(long, env, value_parser = clap::value_parser!(Below10Mode))]
+    obfuscate_below_10_mode: Option<Below10Mode>,
+
+    /// Sensitivity parameter for obfuscating the counts in the Patient stratifier; preset default: 1.
+    #[clap(long, env, value_parser)]
+    delta_patient: Option<f64>,
+
+    /// Sensitivity parameter for obfuscating the counts in the Specimen stratifier; preset default: 20.
+    #[clap(long, env, value_parser)]
+    delta_specimen: Option<f64>,
+
+    /// Sensitivity parameter for obfuscating the counts in the Diagnosis stratifier; preset default: 3.
+    #[clap(long, env, value_parser)]
+    delta_diagnosis: Option<f64>,
+
+    /// Sensitivity parameter for obfuscating the counts in the Procedure stratifier; preset default: 1.7
+    #[clap(long, env, value_parser)]
+    delta_procedures: Option<f64>,
+
+    /// Sensitivity parameter for obfuscating the counts in the Medication Statements stratifier; preset default: 2.1
+    #[clap(long, env, value_parser)]
+    delta_medication_statements: Option<f64>,
+
+    /// Sensitivity parameter for obfuscating the counts in the Histo stratifier; preset default: 20.
+    #[clap(long, env, value_parser)]
+    delta_histo: Option<f64>,
+
+    /// Privacy budget parameter for obfuscating the counts in the stratifiers; preset default: 0.28
+    #[clap(long, env, value_parser)]
+    epsilon: Option<f64>,
+
+    /// The granularity of the rounding of the obfuscated values; preset default: 10
+    #[clap(long, env, value_parser)]
+    rounding_step: Option<usize>,
+}
+
+impl ObfuscationArgs {
+    /// Applies the individually set parameters on top of the preset and validates the result
+    pub fn into_params(self) -> Result<ObfuscationParams, FocusError> {
+        let preset = self.obfuscation_preset.params();
+        let params = ObfuscationParams {
+            obfuscate_zero: self.obfuscate_zero.unwrap_or(preset.obfuscate_zero),
+            below_10_mode: self.obfuscate_below_10_mode.unwrap_or(preset.below_10_mode),
+            delta_patient: self.delta_patient.unwrap_or(preset.delta_patient),
+            delta_specimen: self.delta_specimen.unwrap_or(preset.delta_specimen),
+            delta_diagnosis: self.delta_diagnosis.unwrap_or(preset.delta_diagnosis),
+            delta_procedures: self.delta_procedures.unwrap_or(preset.delta_procedures),
+            delta_medication_statements: self
+                .delta_medication_statements
+                .unwrap_or(preset.delta_medication_statements),
+            delta_histo: self.delta_histo.unwrap_or(preset.delta_histo),
+            epsilon: self.epsilon.unwrap_or(preset.epsilon),
+            rounding_step: self.rounding_step.unwrap_or(preset.rounding_step),
+        };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObfuscationParams {
+    pub obfuscate_zero: bool,
+    pub below_10_mode: Below10Mode,
+    pub delta_patient: f64,
+    pub delta_specimen: f64,
+    pub delta_diagnosis: f64,
+    pub delta_procedures: f64,
+    pub delta_medication_statements: f64,
+    pub delta_histo: f64,
+    pub epsilon: f64,
+    pub rounding_step: usize,
+}
+
+impl ObfuscationParams {
+    pub fn validate(&self) -> Result<(), FocusError> {
+        let positive = [
+            ("delta_patient", self.delta_patient),
+            ("delta_specimen", self.delta_specimen),
+            ("delta_diagnosis", self.delta_diagnosis),
+            ("delta_procedures", self.delta_procedures),
+            (
+                "delta_medication_statements",
+                self.delta_medication_statements,
+            ),
+            ("delta_histo", self.delta_histo),
+            ("epsilon", self.epsilon),
+        ];
+        for (name, value) in positive {
+            if !(value.is_finite() && value > 0.) {
+                return Err(FocusError::ConfigurationError(format!(
+                    "Obfuscation parameter {name} must be a positive number, got {value}"
+                )));
+            }
+        }
+        if self.rounding_step == 0 {
+            return Err(FocusError::ConfigurationError(
+                "Obfuscation parameter rounding_step must be at least 1".into(),
+            ));
+        }
+        if self.obfuscate_zero && self.below_10_mode == Below10Mode::Zero {
+            // a reported zero would then only ever mean a true count between 1 and 9
+            return Err(FocusError::ConfigurationError(
+                "Obfuscating zero counts contradicts reporting counts below 10 as zero".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The parameter set counts are obfuscated with, results and obfuscated values are cached per scope
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ObfuscationScope {
+    Global,
+    Project(String),
+}
+
+/// Caches of obfuscated values per scope, so that no project is answered with values obfuscated using another project's parameters
+#[derive(Default)]
+pub struct ObfCaches(HashMap<ObfuscationScope, ObfCache>);
+
+impl ObfCaches {
+    pub fn get_mut(&mut self, scope: &ObfuscationScope) -> &mut ObfCache {
+        self.0.entry(scope.clone()).or_insert_with(|| ObfCache {
+            cache: Default::default(),
+        })
+    }
+}
+
+/// Obfuscation parameters of a single project in the project obfuscation file; unset parameters default to the preset if one is given, otherwise to the global parameters
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ProjectObfuscation {
+    preset: Option<ObfuscationPreset>,
+    obfuscate_zero: Option<bool>,
+    obfuscate_below_10_mode: Option<Below10Mode>,
+    delta_patient: Option<f64>,
+    delta_specimen: Option<f64>,
+    delta_diagnosis: Option<f64>,
+    delta_procedures: Option<f64>,
+    delta_medication_statements: Option<f64>,
+    delta_histo: Option<f64>,
+    epsilon: Option<f64>,
+    rounding_step: Option<usize>,
+}
+
+/// Parses the project obfuscation file, a JSON object mapping project names as in `Metadata.project` to their parameters
+pub fn parse_project_params(
+    json: &str,
+    global: &ObfuscationParams,
+) -> Result<HashMap<String, ObfuscationParams>, FocusError> {
+    let projects: HashMap<String, ProjectObfuscation> =
+        serde_json::from_str(json).map_err(|e| {
+            FocusError::ConfigurationError(format!("Invalid project obfuscation file: {}", e))
+        })?;
+
+    projects
+        .into_iter()
+        .map(|(project, overrides)| {
+            let base = overrides
+                .preset
+                .map(ObfuscationPreset::params)
+                .unwrap_or_else(|| global.clone());
+            let params = ObfuscationParams {
+                obfuscate_zero: overrides.obfuscate_zero.unwrap_or(base.obfuscate_zero),
+                below_10_mode: overrides
+                    .obfuscate_below_10_mode
+                    .unwrap_or(base.below_10_mode),
+                delta_patient: overrides.delta_patient.unwrap_or(base.delta_patient),
+                delta_specimen: overrides.delta_specimen.unwrap_or(base.delta_specimen),
+                delta_diagnosis: overrides.delta_diagnosis.unwrap_or(base.delta_diagnosis),
+                delta_procedures: overrides.delta_procedures.unwrap_or(base.delta_procedures),
+                delta_medication_statements: overrides
+                    .delta_medication_statements
+                    .unwrap_or(base.delta_medication_statements),
+                delta_histo: overrides.delta_histo.unwrap_or(base.delta_histo),
+                epsilon: overrides.epsilon.unwrap_or(base.epsilon),
+                rounding_step: overrides.rounding_step.unwrap_or(base.rounding_step),
+            };
+            params.validate().map_err(|e| match e {
+                FocusError::ConfigurationError(e) => {
+                    FocusError::ConfigurationError(format!("{} for project {}", e, project))
+                }
+                e => e,
+            })?;
+            Ok((project, params))
+        })
+        .collect()
+}
+
+pub fn read_project_params(
+    path: &Path,
+    global: &ObfuscationParams,
+) -> Result<HashMap<String, ObfuscationParams>, FocusError> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        FocusError::ConfigurationError(format!(
+            "Unable to read project obfuscation file {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    parse_project_params(&content, global)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[clap(flatten)]
+        obfuscation: ObfuscationArgs,
+    }
+
+    fn parse(args: &[&str]) -> Result<ObfuscationParams, FocusError> {
+        TestArgs::try_parse_from([&["focus"], args].concat())
+            .expect("arguments should parse")
+            .obfuscation
+            .into_params()
+    }
+
+    #[test]
+    fn test_default_preset() {
+        pretty_assertions::assert_eq!(parse(&[]).unwrap(), ObfuscationPreset::Default.params());
+    }
+
+    #[test]
+    fn test_override_preset() {
+        let params = parse(&["--obfuscation-preset", "strict", "--epsilon", "0.5"]).unwrap();
+        pretty_assertions::assert_eq!(params.epsilon, 0.5);
+        pretty_assertions::assert_eq!(params.rounding_step, 20);
+        assert!(params.obfuscate_zero);
+    }
+
+    #[test]
+    fn test_override_preset_obfuscate_zero() {
+        assert!(parse(&["--obfuscate-zero"]).unwrap().obfuscate_zero);
+        assert!(parse(&["--obfuscate-zero", "true"]).unwrap().obfuscate_zero);
+        let params = parse(&[
+            "--obfuscation-preset",
+            "strict",
+            "--obfuscate-zero",
+            "false",
+        ])
+        .unwrap();
+        assert!(!params.obfuscate_zero);
+    }
+
+    #[test]
+    fn test_numeric_below_10_mode() {
+        let params = parse(&["--obfuscate-below-10-mode", "2"]).unwrap();
+        pretty_assertions::assert_eq!(params.below_10_mode, Below10Mode::Obfuscate);
+        assert!(TestArgs::try_parse_from(["focus", "--obfuscate-below-10-mode", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        assert!(parse(&["--epsilon", "0"]).is_err());
+        assert!(parse(&["--delta-histo=-1"]).is_err());
+        assert!(parse(&["--rounding-step", "0"]).is_err());
+        assert!(parse(&["--obfuscate-zero", "--obfuscate-below-10-mode", "zero"]).is_err());
+    }
+
+    #[test]
+    fn test_project_params() {
+        let global = ObfuscationPreset::Default.params();
+        let projects = parse_project_params(
+            r#"{"bbmri": {"epsilon": 0.2}, "dktk": {"preset": "strict", "obfuscate_below_10_mode": "obfuscate"}}"#,
+            &global,
+        )
+        .unwrap();
+
+        pretty_assertions::assert_eq!(
+            projects["bbmri"],
+            ObfuscationParams {
+                epsilon: 0.2,
+                ..global.clone()
+            }
+        );
+        pretty_assertions::assert_eq!(
+            projects["dktk"],
+            ObfuscationParams {
+                below_10_mode: Below10Mode::Obfuscate,
+                ..ObfuscationPreset::Strict.params()
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_project_params() {
+        let global = ObfuscationPreset::Default.params();
+        assert!(parse_project_params(r#"{"bbmri": {"epsilon": -1}}"#, &global).is_err());
+        assert!(parse_project_params(r#"{"bbmri": {"epsilion": 0.2}}"#, &global).is_err());
+    }
+}
@@ -42,6 +42,7 @@ pub static CRITERION_CODE_LISTS: LazyLock<HashMap<&'static str, Vec<&'static str
     LazyLock::new(|| {
         HashMap::from([
             ("diagnosis", vec!["icd10", "icd10gm", "icd10gmnew"]),
+            ("diagnosis_old", vec!["icd10"]),
             ("body_weight", vec!["loinc"]),
             ("bmi", vec!["loinc"]),
             ("smoking_status", vec!["loinc"]),
@@ -51,6 +52,10 @@ pub static CRITERION_CODE_LISTS: LazyLock<HashMap<&'static str, Vec<&'static str
         ])
     });
 
+/// Units numeric values of criteria are converted to, the snippets compare quantities in these
+pub static CRITERION_UNITS: LazyLock<HashMap<&'static str, &'static str>> =
+    LazyLock::new(|| HashMap::from([("body_weight", "kg"), ("bmi", "kg/m2")]));
+
 pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'static str>> =
     LazyLock::new(|| {
         HashMap::from([
@@ -69,20 +74,17 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
             "exists from [Condition] C\nwhere AgeInYearsAt(FHIRHelpers.ToDateTime(C.onset)) between Ceiling({{D1}}) and Ceiling({{D2}})",
         ),
         (("donor_age", CriterionRole::Query), " AgeInYears() between Ceiling({{D1}}) and Ceiling({{D2}})"),
-        (
-            ("observationRange", CriterionRole::Query),
-            "exists from [Observation: Code '{{K}}' from {{A1}}] O\nwhere O.value between {{D1}} and {{D2}}",
-        ),
         (
             ("body_weight", CriterionRole::Query),
-            "exists from [Observation: Code '{{K}}' from {{A1}}] O\nwhere ((O.value as Quantity) < {{D1}} 'kg' and (O.value as Quantity) > {{D2}} 'kg')",
+            "exists from [Observation: Code '{{K}}' from {{A1}}] O\nwhere (O.value as Quantity) between {{D1}} and {{D2}}",
         ),
         (
             ("bmi", CriterionRole::Query),
-            "exists from [Observation: Code '{{K}}' from {{A1}}] O\nwhere ((O.value as Quantity) < {{D1}} 'kg/m2' and (O.value as Quantity) > {{D2}} 'kg/m2')",
+            "exists from [Observation: Code '{{K}}' from {{A1}}] O\nwhere (O.value as Quantity) between {{D1}} and {{D2}}",
         ),
         (("sample_kind", CriterionRole::Query), " exists [Specimen: Code '{{C}}' from {{A1}}]"),
         (("sample_kind", CriterionRole::Filter), " (S.type.coding.code contains '{{C}}')"),
+        (("sample_kind", CriterionRole::Retrieve), "[Specimen: Code '{{C}}' from {{A1}}]"),
         (
             ("storage_temperature", CriterionRole::Filter),
             "(S.extension.where(url='https://fhir.bbmri.de/StructureDefinition/StorageTemperature').value.coding.code contains '{{C}}')",
@@ -103,10 +105,18 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
             ("fasting_status", CriterionRole::Query),
             "exists from [Specimen] S\nwhere S.collection.fastingStatus.coding.code contains '{{C}}' ",
         ),
+        (
+            ("fasting_status", CriterionRole::Retrieve),
+            "[Specimen] S\nwhere S.collection.fastingStatus.coding.code contains '{{C}}'",
+        ),
         (
             ("storage_temperature", CriterionRole::Query),
             "exists from [Specimen] S where (S.extension.where(url='https://fhir.bbmri.de/StructureDefinition/StorageTemperature').value.coding contains Code '{{C}}' from {{A1}}) ",
         ),
+        (
+            ("storage_temperature", CriterionRole::Retrieve),
+            "[Specimen] S where (S.extension.where(url='https://fhir.bbmri.de/StructureDefinition/StorageTemperature').value.coding contains Code '{{C}}' from {{A1}})",
+        ),
         (
             ("smoking_status", CriterionRole::Query),
             "exists from [Observation: Code '{{K}}' from {{A1}}] O\nwhere O.value.coding.code contains '{{C}}' ",
@@ -114,11 +124,41 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
     ])
     });
 
+pub static ENTITY_SOURCES: LazyLock<HashMap<&'static str, &'static str>> =
+    LazyLock::new(|| HashMap::from([("specimen", "[Specimen] S")]));
+
+pub static ENTITY_SNIPPETS: LazyLock<HashMap<(&'static str, &'static str), &'static str>> =
+    LazyLock::new(|| {
+        HashMap::from([
+        (("specimen", "sample_kind"), "S.type.coding.code contains '{{C}}'"),
+        (
+            ("specimen", "storage_temperature"),
+            "S.extension.where(url='https://fhir.bbmri.de/StructureDefinition/StorageTemperature').value.coding.code contains '{{C}}'",
+        ),
+        (
+            ("specimen", "sampling_date"),
+            "FHIRHelpers.ToDateTime(S.collection.collected) between {{D1}} and {{D2}}",
+        ),
+        (
+            ("specimen", "fasting_status"),
+            "S.collection.fastingStatus.coding.code contains '{{C}}'",
+        ),
+        (
+            ("specimen", "diagnosis"),
+            "S.extension.where(url='https://fhir.bbmri.de/StructureDefinition/SampleDiagnosis').value.coding.code contains '{{C}}'",
+        ),
+    ])
+    });
+
 pub static MANDATORY_CODE_LISTS: LazyLock<IndexSet<&'static str>> =
     LazyLock::new(|| IndexSet::from(["icd10", "SampleMaterialType"]));
 
-pub static SAMPLE_TYPE_WORKAROUNDS: LazyLock<HashMap<&'static str, Vec<&'static str>>> =
-    LazyLock::new(|| {
+/// Values Lens sends that stand for several values in the data, per criterion
+pub static VALUE_MAPPINGS: LazyLock<
+    HashMap<&'static str, HashMap<&'static str, Vec<&'static str>>>,
+> = LazyLock::new(|| {
+    HashMap::from([(
+        "sample_kind",
         HashMap::from([
             (
                 "blood-plasma",
@@ -153,5 +193,6 @@ pub static SAMPLE_TYPE_WORKAROUNDS: LazyLock<HashMap<&'static str, Vec<&'static
             ("tissue-other", vec!["tissue-paxgene-or-else", "tissue"]),
             ("derivative-other", vec!["derivative"]),
             ("liquid-other", vec!["liquid"]),
-        ])
-    });
+        ]),
+    )])
+});
@@ -127,9 +127,18 @@ pub static OBSERVATION_LOINC_CODES: LazyLock<HashMap<&'static str, &'static str>
             (VITAL_STATUS_CS, "75186-7"),
             ("grading", "59542-1"),
             ("morphology", "59847-4"),
-            ("body_weight", "29463-7"),
-            ("bmi", "39156-5"),
-            ("smoking_status", "72166-2"),
+            ("responseOverTime", "21976-6"),
+            ("localRegionalRecurrence", "LA4583-6"),
+            ("lymphNodeRecurrence", "LA4370-8"),
+            ("distantMetastases", "LA4226-2"),
+            ("observationMolecularMarkerName", "48018-6"),
+            ("observationMolecularMarkerAminoacidchange", "48005-3"),
+            ("observationMolecularMarkerDNAchange", "81290-9"),
+            ("observationMolecularMarkerSeqRefNCBI", "81248-7"),
+            ("observationMolecularMarkerEnsemblID", "81249-5"),
+            // ("body_weight", "29463-7"),
+            // ("bmi", "39156-5"),
+            // ("smoking_status", "72166-2"),
         ])
     });
 
@@ -142,7 +151,8 @@ pub static CRITERION_CODE_LISTS: LazyLock<HashMap<&'static str, Vec<&'static str
             ("conditionLocalization", vec![LOCALIZATION_ICD_O_3]),
             ("grading", vec![LOINC, GRADING_CS]),
             ("metastases_present", vec![LOINC, YNU_CS]),
-            // ("localization_metastases", vec![LOINC, "fmlokalisationcs"]),
+            // TODO: add "fmlokalisationcs" once CCE defines it
+            ("localization_metastases", vec![LOINC]),
             ("procedure", vec![THERAPY_TYPE_CS]),
             ("medicationStatement", vec![THERAPY_TYPE_CS]),
             ("morphology", vec![LOINC, MORPH]),
@@ -151,10 +161,10 @@ pub static CRITERION_CODE_LISTS: LazyLock<HashMap<&'static str, Vec<&'static str
                 "observationMolecularMarkerName",
                 vec![LOINC, MOLECULAR_MARKER],
             ),
-            // ("observationMolecularMarkerAminoacidchange", vec![LOINC]),
-            // ("observationMolecularMarkerDNAchange", vec![LOINC]),
-            // ("observationMolecularMarkerSeqRefNCBI", vec![LOINC]),
-            // ("observationMolecularMarkerEnsemblID", vec![LOINC]),
+            ("observationMolecularMarkerAminoacidchange", vec![LOINC]),
+            ("observationMolecularMarkerDNAchange", vec![LOINC]),
+            ("observationMolecularMarkerSeqRefNCBI", vec![LOINC]),
+            ("observationMolecularMarkerEnsemblID", vec![LOINC]),
             (
                 "local_assessment_residual_tumor",
                 vec![THERAPY_TYPE_CS, LOCAL_ASSESSMENT_RESIDUAL_TUMOR_CS],
@@ -163,18 +173,10 @@ pub static CRITERION_CODE_LISTS: LazyLock<HashMap<&'static str, Vec<&'static str
                 "responseOverTime",
                 vec![LOINC, OVERALL_ASSESSMENT_RESIDUAL_TUMOR_CS],
             ),
-            // (
-            //     "localRegionalRecurrence",
-            //     vec![LOINC, "verlauflokalertumorstatuscs"],
-            // ),
-            // (
-            //     "lymphNodeRecurrence",
-            //     vec![LOINC, "verlauftumorstatuslymphknotencs"],
-            // ),
-            // (
-            //     "distantMetastases",
-            //     vec![LOINC, "verlauftumorstatusfernmetastasencs"],
-            // ),
+            // TODO: add the "verlauf...cs" code lists once CCE defines them, the values are matched by code until then
+            ("localRegionalRecurrence", vec![LOINC]),
+            ("lymphNodeRecurrence", vec![LOINC]),
+            ("distantMetastases", vec![LOINC]),
             (VITAL_STATUS_CS, vec![LOINC, VITAL_STATUS_CS]),
             ("TNM-T", vec![LOINC, TNM_T_CS]),
             ("TNM-N", vec![LOINC, TNM_N_CS]),
@@ -322,6 +324,18 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
                 ("medicationStatement", CriterionRole::Query),
                 "exists [MedicationStatement: category in Code '{{C}}' from {{A1}}]",
             ),
+            (
+                ("diagnosis", CriterionRole::Event),
+                "[Condition: Code '{{C}}' from {{A1}}] C\nreturn FHIRHelpers.ToDateTime(C.onset)",
+            ),
+            (
+                ("procedure", CriterionRole::Event),
+                "[Procedure: category in Code '{{C}}' from {{A1}}] P\nreturn Coalesce(FHIRHelpers.ToDateTime(P.performed as dateTime), start of FHIRHelpers.ToInterval(P.performed as Period))",
+            ),
+            (
+                ("medicationStatement", CriterionRole::Event),
+                "[MedicationStatement: category in Code '{{C}}' from {{A1}}] M\nreturn Coalesce(FHIRHelpers.ToDateTime(M.effective as dateTime), start of FHIRHelpers.ToInterval(M.effective as Period))",
+            ),
             (
                 ("local_assessment_residual_tumor", CriterionRole::Query),
                 "exists from [Procedure: category in Code 'OP' from {{A1}}] P\nwhere P.outcome.coding.code contains '{{C}}'",
@@ -380,8 +394,3 @@ pub static MANDATORY_CODE_LISTS: LazyLock<IndexSet<&'static str>> = LazyLock::ne
         VITAL_STATUS_CS,
     ])
 });
-
-pub static SAMPLE_TYPE_WORKAROUNDS: LazyLock<HashMap<&'static str, Vec<&'static str>>> =
-    LazyLock::new(|| {
-        HashMap::new() // No workarounds for cce
-    });
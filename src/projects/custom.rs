@@ -0,0 +1,215 @@
+use std::{collections::HashMap, path::Path, sync::OnceLock};
+
+use indexmap::IndexSet;
+use serde::Deserialize;
+
+use super::{lint::check_project, CriterionRole, Project};
+use crate::errors::FocusError;
+
+/// Projects loaded at startup, they take precedence over the compiled-in ones of the same name
+static CUSTOM_PROJECTS: OnceLock<HashMap<String, &'static CustomProject>> = OnceLock::new();
+
+/// A project defined in a directory instead of in Rust, with the same tables as the compiled-in projects.
+/// Definitions are loaded once and live as long as Focus, so their strings are leaked to be `'static` like the compiled-in ones
+#[derive(Default)]
+pub struct CustomProject {
+    pub(crate) code_lists: HashMap<&'static str, &'static str>,
+    pub(crate) observation_loinc_codes: HashMap<&'static str, &'static str>,
+    pub(crate) value_mappings: HashMap<&'static str, HashMap<&'static str, Vec<&'static str>>>,
+    pub(crate) criterion_code_lists: HashMap<&'static str, Vec<&'static str>>,
+    pub(crate) cql_snippets: HashMap<(&'static str, CriterionRole), &'static str>,
+    pub(crate) criterion_units: HashMap<&'static str, &'static str>,
+    pub(crate) entity_sources: HashMap<&'static str, &'static str>,
+    pub(crate) entity_snippets: HashMap<(&'static str, &'static str), &'static str>,
+    pub(crate) mandatory_code_lists: IndexSet<&'static str>,
+    pub(crate) cql_template: &'static str,
+    pub(crate) body: &'static str,
+}
+
+/// Contents of project.json, snippets are keyed by criterion and role, entity snippets by entity and criterion, value mappings by criterion and value
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ProjectFile {
+    #[serde(default)]
+    code_lists: HashMap<String, String>,
+    #[serde(default)]
+    observation_loinc_codes: HashMap<String, String>,
+    #[serde(default)]
+    value_mappings: HashMap<String, HashMap<String, Vec<String>>>,
+    #[serde(default)]
+    criterion_code_lists: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    cql_snippets: HashMap<String, HashMap<CriterionRole, String>>,
+    #[serde(default)]
+    criterion_units: HashMap<String, String>,
+    #[serde(default)]
+    entity_sources: HashMap<String, String>,
+    #[serde(default)]
+    entity_snippets: HashMap<String, HashMap<String, String>>,
+    #[serde(default)]
+    mandatory_code_lists: Vec<String>,
+}
+
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+fn leak_map(map: HashMap<String, String>) -> HashMap<&'static str, &'static str> {
+    map.into_iter().map(|(k, v)| (leak(k), leak(v))).collect()
+}
+
+fn leak_lists(map: HashMap<String, Vec<String>>) -> HashMap<&'static str, Vec<&'static str>> {
+    map.into_iter()
+        .map(|(k, v)| (leak(k), v.into_iter().map(leak).collect()))
+        .collect()
+}
+
+impl From<ProjectFile> for CustomProject {
+    fn from(file: ProjectFile) -> Self {
+        let mut cql_snippets = HashMap::new();
+        for (key, snippets) in file.cql_snippets {
+            let key = leak(key);
+            for (role, snippet) in snippets {
+                cql_snippets.insert((key, role), leak(snippet));
+            }
+        }
+        let mut entity_snippets = HashMap::new();
+        for (entity, snippets) in file.entity_snippets {
+            let entity = leak(entity);
+            for (key, snippet) in snippets {
+                entity_snippets.insert((entity, leak(key)), leak(snippet));
+            }
+        }
+
+        CustomProject {
+            code_lists: leak_map(file.code_lists),
+            observation_loinc_codes: leak_map(file.observation_loinc_codes),
+            value_mappings: file
+                .value_mappings
+                .into_iter()
+                .map(|(key, mappings)| (leak(key), leak_lists(mappings)))
+                .collect(),
+            criterion_code_lists: leak_lists(file.criterion_code_lists),
+            cql_snippets,
+            criterion_units: leak_map(file.criterion_units),
+            entity_sources: leak_map(file.entity_sources),
+            entity_snippets,
+            mandatory_code_lists: file.mandatory_code_lists.into_iter().map(leak).collect(),
+            cql_template: "",
+            body: "",
+        }
+    }
+}
+
+/// Reads a project from its directory with project.json, template.cql and body.json like the compiled-in projects
+fn read_project(dir: &Path) -> Result<CustomProject, FocusError> {
+    let read = |file: &str| {
+        std::fs::read_to_string(dir.join(file)).map_err(|e| {
+            FocusError::InvalidProjectDefinition(format!(
+                "Unable to read {}: {}",
+                dir.join(file).display(),
+                e
+            ))
+        })
+    };
+    let file: ProjectFile = serde_json::from_str(&read("project.json")?).map_err(|e| {
+        FocusError::InvalidProjectDefinition(format!(
+            "Unable to parse {}: {}",
+            dir.join("project.json").display(),
+            e
+        ))
+    })?;
+
+    Ok(CustomProject {
+        cql_template: leak(read("template.cql")?),
+        body: leak(read("body.json")?),
+        ..file.into()
+    })
+}
+
+/// Reads and checks all projects in the subdirectories of `dir`, named after their directory
+fn read_projects(dir: &Path) -> Result<HashMap<String, &'static CustomProject>, FocusError> {
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        FocusError::InvalidProjectDefinition(format!(
+            "Unable to read projects directory {}: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+
+    let mut projects = HashMap::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| FocusError::InvalidProjectDefinition(e.to_string()))?
+            .path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let project: &'static CustomProject = Box::leak(Box::new(read_project(&path)?));
+
+        let problems = check_project(&Project::Custom(project));
+        if !problems.is_empty() {
+            return Err(FocusError::InvalidProjectDefinition(format!(
+                "{}: {}",
+                name,
+                problems.join("; ")
+            )));
+        }
+        projects.insert(name, project);
+    }
+    Ok(projects)
+}
+
+/// Loads the projects in `dir` so they can be parsed like the compiled-in ones, returns their names
+pub fn load_projects(dir: &Path) -> Result<Vec<String>, FocusError> {
+    let projects = read_projects(dir)?;
+    let mut names: Vec<String> = projects.keys().cloned().collect();
+    names.sort();
+    CUSTOM_PROJECTS.set(projects).map_err(|_| {
+        FocusError::InvalidProjectDefinition("Projects can only be loaded once".into())
+    })?;
+    Ok(names)
+}
+
+pub(super) fn names() -> Vec<String> {
+    let mut names: Vec<String> = CUSTOM_PROJECTS
+        .get()
+        .into_iter()
+        .flat_map(|projects| projects.keys().cloned())
+        .collect();
+    names.sort();
+    names
+}
+
+pub(super) fn get(name: &str) -> Option<&'static CustomProject> {
+    CUSTOM_PROJECTS.get()?.get(name).copied()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_projects() {
+        let projects = read_projects(Path::new("resources/test/projects")).unwrap();
+        let project = Project::Custom(projects["minimal"]);
+
+        pretty_assertions::assert_eq!(
+            project.get_cql_snippets()[&("gender", CriterionRole::Query)],
+            "Patient.gender = '{{C}}'"
+        );
+        pretty_assertions::assert_eq!(
+            project.get_criterion_code_lists()["diagnosis"],
+            vec!["icd10"]
+        );
+        pretty_assertions::assert_eq!(
+            project.get_value_mappings()["gender"]["other"],
+            vec!["unknown"]
+        );
+    }
+}
@@ -302,6 +302,30 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
             ("medicationStatement", CriterionRole::Query),
             "exists [MedicationStatement: category in Code '{{C}}' from {{A1}}]",
         ),
+        (
+            ("diagnosis", CriterionRole::Event),
+            "[Condition: Code '{{C}}' from {{A1}}] C\nreturn FHIRHelpers.ToDateTime(C.onset)",
+        ),
+        (
+            ("procedure", CriterionRole::Event),
+            "[Procedure: category in Code '{{C}}' from {{A1}}] P\nreturn Coalesce(FHIRHelpers.ToDateTime(P.performed as dateTime), start of FHIRHelpers.ToInterval(P.performed as Period))",
+        ),
+        (
+            ("medicationStatement", CriterionRole::Event),
+            "[MedicationStatement: category in Code '{{C}}' from {{A1}}] M\nreturn Coalesce(FHIRHelpers.ToDateTime(M.effective as dateTime), start of FHIRHelpers.ToInterval(M.effective as Period))",
+        ),
+        (
+            ("diagnosis", CriterionRole::Retrieve),
+            "[Condition: Code '{{C}}' from {{A1}}]",
+        ),
+        (
+            ("procedure", CriterionRole::Retrieve),
+            "[Procedure: category in Code '{{C}}' from {{A1}}]",
+        ),
+        (
+            ("medicationStatement", CriterionRole::Retrieve),
+            "[MedicationStatement: category in Code '{{C}}' from {{A1}}]",
+        ),
         (
             ("local_assessment_residual_tumor", CriterionRole::Query),
             "exists from [Procedure: category in Code 'OP' from {{A1}}] P\nwhere P.outcome.coding.code contains '{{C}}'",
@@ -314,6 +338,10 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
             ("sample_kind", CriterionRole::Filter),
             "(S.type.coding.code contains '{{C}}')",
         ),
+        (
+            ("sample_kind", CriterionRole::Retrieve),
+            "[Specimen: Code '{{C}}' from {{A1}}]",
+        ),
         (
             ("retrieveSpecimenByType", CriterionRole::Query),
             "(S.type.coding.code contains '{{C}}')",
@@ -361,10 +389,34 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
     ])
     });
 
-pub static MANDATORY_CODE_LISTS: LazyLock<IndexSet<&'static str>> =
-    LazyLock::new(|| IndexSet::from(["loinc"]));
+pub static ENTITY_SOURCES: LazyLock<HashMap<&'static str, &'static str>> =
+    LazyLock::new(|| HashMap::from([("condition", "[Condition] C"), ("specimen", "[Specimen] S")]));
 
-pub static SAMPLE_TYPE_WORKAROUNDS: LazyLock<HashMap<&'static str, Vec<&'static str>>> =
+pub static ENTITY_SNIPPETS: LazyLock<HashMap<(&'static str, &'static str), &'static str>> =
     LazyLock::new(|| {
-        HashMap::new() // No workarounds for dktk
+        HashMap::from([
+            (
+                ("condition", "diagnosis"),
+                "C.code.coding contains Code '{{C}}' from {{A1}}",
+            ),
+            (
+                ("condition", "conditionLocalization"),
+                "C.bodySite.coding.code contains '{{C}}'",
+            ),
+            (
+                ("condition", "year_of_diagnosis"),
+                "year from C.onset between {{D1}} and {{D2}}",
+            ),
+            (
+                ("condition", "age_at_diagnosis"),
+                "AgeInYearsAt(FHIRHelpers.ToDateTime(C.onset)) between {{D1}} and {{D2}}",
+            ),
+            (
+                ("specimen", "sample_kind"),
+                "S.type.coding.code contains '{{C}}'",
+            ),
+        ])
     });
+
+pub static MANDATORY_CODE_LISTS: LazyLock<IndexSet<&'static str>> =
+    LazyLock::new(|| IndexSet::from(["loinc"]));
@@ -26,6 +26,10 @@ pub static CODE_LISTS: LazyLock<HashMap<&'static str, &'static str>> = LazyLock:
             "bodySite",
             "http://dktk.dkfz.de/fhir/onco/core/CodeSystem/SeitenlokalisationCS",
         ),
+        (
+            "specimentype",
+            "https://fhir.bbmri.de/CodeSystem/SampleMaterialType",
+        ),
         (
             "bodySiteNNGM",
             "http://terminology.hl7.org/CodeSystem/icd-o-3",
@@ -364,6 +368,18 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
             ("medicationStatement", CriterionRole::Query),
             "exists [MedicationStatement: category in Code '{{C}}' from {{A1}}]",
         ),
+        (
+            ("diagnosis", CriterionRole::Event),
+            "[Condition: Code '{{C}}' from {{A1}}] C\nreturn FHIRHelpers.ToDateTime(C.onset)",
+        ),
+        (
+            ("procedure", CriterionRole::Event),
+            "[Procedure: category in Code '{{C}}' from {{A1}}] P\nreturn Coalesce(FHIRHelpers.ToDateTime(P.performed as dateTime), start of FHIRHelpers.ToInterval(P.performed as Period))",
+        ),
+        (
+            ("medicationStatement", CriterionRole::Event),
+            "[MedicationStatement: category in Code '{{C}}' from {{A1}}] M\nreturn Coalesce(FHIRHelpers.ToDateTime(M.effective as dateTime), start of FHIRHelpers.ToInterval(M.effective as Period))",
+        ),
         (
             ("local_assessment_residual_tumor", CriterionRole::Query),
             "exists from [Procedure: category in Code 'OP' from {{A1}}] P\nwhere P.outcome.coding.code contains '{{C}}'",
@@ -425,8 +441,3 @@ pub static CQL_SNIPPETS: LazyLock<HashMap<(&'static str, CriterionRole), &'stati
 
 pub static MANDATORY_CODE_LISTS: LazyLock<IndexSet<&'static str>> =
     LazyLock::new(|| IndexSet::from(["loinc"]));
-
-pub static SAMPLE_TYPE_WORKAROUNDS: LazyLock<HashMap<&'static str, Vec<&'static str>>> =
-    LazyLock::new(|| {
-        HashMap::new() // No workarounds for dktk
-    });
@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+
+use super::Project;
+use crate::errors::FocusError;
+
+/// Finds what would make the CQL generated for a project invalid: placeholders the CQL generator won't fill,
+/// code lists and code systems that are not defined, and tables or snippets for criteria and entities that don't exist
+pub fn check_project(project: &Project) -> Vec<String> {
+    let mut problems = Vec::new();
+    let code_lists = project.get_code_lists();
+    let mandatory_code_lists = project.get_mandatory_code_lists();
+
+    for (key, lists) in project.get_criterion_code_lists() {
+        for list in lists {
+            if !code_lists.contains_key(list) {
+                problems.push(format!(
+                    "Criterion {} uses undefined code list {}",
+                    key, list
+                ));
+            }
+        }
+    }
+    for list in mandatory_code_lists {
+        if !code_lists.contains_key(list) {
+            problems.push(format!("Mandatory code list {} is undefined", list));
+        }
+    }
+
+    // criteria are defined by their snippets, all other tables only add to those
+    let criteria: HashSet<&str> = project
+        .get_cql_snippets()
+        .keys()
+        .map(|(key, _)| *key)
+        .chain(project.get_entity_snippets().keys().map(|(_, key)| *key))
+        .collect();
+    for (table, keys) in [
+        (
+            "Code lists",
+            project
+                .get_criterion_code_lists()
+                .keys()
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "LOINC code",
+            project
+                .get_observation_loinc_codes()
+                .keys()
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "Unit",
+            project.get_criterion_units().keys().collect::<Vec<_>>(),
+        ),
+        (
+            "Value mappings",
+            project.get_value_mappings().keys().collect::<Vec<_>>(),
+        ),
+    ] {
+        for key in keys {
+            if !criteria.contains(key) {
+                problems.push(format!("{} of undefined criterion {}", table, key));
+            }
+        }
+    }
+
+    let mut snippets: Vec<(String, &str, &str)> = project
+        .get_cql_snippets()
+        .iter()
+        .map(|((key, _), snippet)| (format!("Snippet of {}", key), *key, *snippet))
+        .collect();
+    for ((entity, key), snippet) in project.get_entity_snippets() {
+        if !project.get_entity_sources().contains_key(entity) {
+            problems.push(format!(
+                "Snippet of {} is for undefined entity {}",
+                key, entity
+            ));
+        }
+        snippets.push((
+            format!("Snippet of {} for entity {}", key, entity),
+            key,
+            snippet,
+        ));
+    }
+    for (name, key, snippet) in snippets {
+        let code_list_count = project
+            .get_criterion_code_lists()
+            .get(key)
+            .map_or(0, Vec::len);
+        for placeholder in placeholders(snippet) {
+            let known = match placeholder {
+                "C" | "D" | "D1" | "D2" => true,
+                "K" => project.get_observation_loinc_codes().contains_key(key),
+                _ => placeholder
+                    .strip_prefix('A')
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .is_some_and(|index| (1..=code_list_count).contains(&index)),
+            };
+            if !known {
+                problems.push(format!(
+                    "{} has unknown placeholder {{{{{}}}}}",
+                    name, placeholder
+                ));
+            }
+        }
+        // only mandatory code lists are declared whatever the query, others only for the criteria they belong to
+        for alias in code_system_aliases(snippet) {
+            if !mandatory_code_lists.contains(alias) {
+                problems.push(format!(
+                    "{} uses code system {} that is not mandatory",
+                    name, alias
+                ));
+            }
+        }
+    }
+
+    for (file, content, required) in [
+        (
+            "CQL template",
+            project.get_cql_template(),
+            &["lists", "retrieval_criteria"][..],
+        ),
+        (
+            "Body",
+            project.get_body(),
+            &["LIBRARY_ENCODED", "LIBRARY_UUID", "MEASURE_UUID"][..],
+        ),
+    ] {
+        let found = placeholders(content);
+        for placeholder in required {
+            if !found.contains(placeholder) {
+                problems.push(format!(
+                    "{} lacks placeholder {{{{{}}}}}",
+                    file, placeholder
+                ));
+            }
+        }
+        for placeholder in found {
+            if !required.contains(&placeholder) && placeholder != "filter_criteria" {
+                problems.push(format!(
+                    "{} has unknown placeholder {{{{{}}}}}",
+                    file, placeholder
+                ));
+            }
+        }
+    }
+
+    problems.sort();
+    problems.dedup();
+    problems
+}
+
+/// Checks the named projects, each problem prefixed with the project it was found in
+pub fn check_projects<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<String>, FocusError> {
+    let mut problems = Vec::new();
+    for name in names {
+        let project: Project = name.parse()?;
+        problems.extend(
+            check_project(&project)
+                .into_iter()
+                .map(|problem| format!("{}: {}", name, problem)),
+        );
+    }
+    Ok(problems)
+}
+
+/// Names of the {{...}} placeholders in a snippet or template
+fn placeholders(text: &str) -> Vec<&str> {
+    text.split("{{")
+        .skip(1)
+        .filter_map(|part| part.split_once("}}").map(|(name, _)| name))
+        .collect()
+}
+
+/// Code system aliases written out in a snippet instead of filled in, as in Code 'male' from loinc
+fn code_system_aliases(text: &str) -> Vec<&str> {
+    text.split("' from ")
+        .skip(1)
+        .map(|part| {
+            part.split(|c: char| !c.is_alphanumeric() && c != '_')
+                .next()
+                .unwrap_or_default()
+        })
+        .filter(|alias| !alias.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::projects::{CriterionRole, CustomProject, COMPILED_IN};
+
+    #[test]
+    fn test_check_project() {
+        let project = Project::Custom(Box::leak(Box::new(CustomProject {
+            criterion_code_lists: HashMap::from([("diagnosis", vec!["icd10"])]),
+            observation_loinc_codes: HashMap::from([("body_height", "8302-2")]),
+            cql_snippets: HashMap::from([
+                (
+                    ("diagnosis", CriterionRole::Query),
+                    "[Condition: Code '{{C}}' from {{A2}}]",
+                ),
+                (
+                    ("body_weight", CriterionRole::Query),
+                    "[Observation: Code '{{K}}' from loinc]",
+                ),
+            ]),
+            cql_template: "{{lists}} {{retrieval_criteria}} {{criteria}}",
+            body: "{{LIBRARY_ENCODED}} {{LIBRARY_UUID}} {{MEASURE_UUID}}",
+            ..Default::default()
+        })));
+
+        pretty_assertions::assert_eq!(
+            check_project(&project),
+            vec![
+                "CQL template has unknown placeholder {{criteria}}",
+                "Criterion diagnosis uses undefined code list icd10",
+                "LOINC code of undefined criterion body_height",
+                "Snippet of body_weight has unknown placeholder {{K}}",
+                "Snippet of body_weight uses code system loinc that is not mandatory",
+                "Snippet of diagnosis has unknown placeholder {{A2}}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compiled_in_projects() {
+        // pscc does not define the code systems of these criteria yet
+        let known_problems = [
+            "pscc: Code lists of undefined criterion icd-o-3-morphology",
+            "pscc: Criterion TNM-M uses undefined code list TNMMCS",
+            "pscc: Criterion TNM-N uses undefined code list TNMNCS",
+            "pscc: Criterion TNM-T uses undefined code list TNMTCS",
+            "pscc: Criterion TNM-m-Symbol uses undefined code list TNMmSymbolCS",
+            "pscc: Criterion TNM-r-Symbol uses undefined code list TNMrSymbolCS",
+            "pscc: Criterion TNM-y-Symbol uses undefined code list TNMySymbolCS",
+            "pscc: Criterion bodySite uses undefined code list bodySite",
+            "pscc: Criterion distantMetastases uses undefined code list verlauftumorstatusfernmetastasencs",
+            "pscc: Criterion grading uses undefined code list gradingcs",
+            "pscc: Criterion localRegionalRecurrence uses undefined code list verlauflokalertumorstatuscs",
+            "pscc: Criterion local_assessment_residual_tumor uses undefined code list lokalebeurteilungresidualstatuscs",
+            "pscc: Criterion localization_metastases uses undefined code list fmlokalisationcs",
+            "pscc: Criterion lymphNodeRecurrence uses undefined code list verlauftumorstatuslymphknotencs",
+            "pscc: Criterion metastases_present uses undefined code list jnucs",
+            "pscc: Criterion morphology uses undefined code list morph",
+            "pscc: Criterion responseOverTime uses undefined code list gesamtbeurteilungtumorstatuscs",
+            "pscc: Criterion sample_kind uses undefined code list specimentype",
+        ];
+
+        pretty_assertions::assert_eq!(check_projects(COMPILED_IN).unwrap(), known_problems);
+        assert!(check_projects(["nonexistent"]).is_err());
+    }
+}
@@ -1,21 +1,42 @@
-use std::{collections::HashMap, hash::Hash, str::FromStr};
+use std::{collections::HashMap, hash::Hash, str::FromStr, sync::LazyLock};
 
 use indexmap::IndexSet;
+use serde::Deserialize;
 
 use crate::errors::FocusError;
 
+pub use custom::{load_projects, CustomProject};
+pub use lint::check_projects;
+
 mod bbmri;
 mod cce;
+mod custom;
 mod dhki;
 mod dktk;
 mod itcc;
+mod lint;
 mod nngm;
 mod pscc;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Copy)]
+static NO_VALUE_MAPPINGS: LazyLock<
+    HashMap<&'static str, HashMap<&'static str, Vec<&'static str>>>,
+> = LazyLock::new(HashMap::new);
+static NO_CRITERION_UNITS: LazyLock<HashMap<&'static str, &'static str>> =
+    LazyLock::new(HashMap::new);
+static NO_ENTITY_SOURCES: LazyLock<HashMap<&'static str, &'static str>> =
+    LazyLock::new(HashMap::new);
+static NO_ENTITY_SNIPPETS: LazyLock<HashMap<(&'static str, &'static str), &'static str>> =
+    LazyLock::new(HashMap::new);
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CriterionRole {
     Query,
     Filter,
+    Event,       // dates at which the criterion applied, for temporal relations
+    Retrieve,    // resources matching the criterion, for counts
+    LowerThan,   // strictly lower than {{D}}, where the query range can't be rewritten
+    GreaterThan, // strictly greater than {{D}}, likewise
 }
 
 pub enum Project {
@@ -25,13 +46,31 @@ pub enum Project {
     Dhki,
     Nngm,
     Itcc,
-	Pscc,
+    Pscc,
+    Custom(&'static CustomProject), // loaded from the projects directory at startup
+}
+
+/// Names of the projects compiled into Focus
+pub const COMPILED_IN: [&str; 7] = ["bbmri", "dktk", "cce", "dhki", "nngm", "itcc", "pscc"];
+
+/// Names of all projects, the compiled-in ones and those loaded from the projects directory
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = COMPILED_IN.iter().map(|name| name.to_string()).collect();
+    for name in custom::names() {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
 }
 
 impl FromStr for Project {
     type Err = FocusError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(project) = custom::get(s) {
+            return Ok(Project::Custom(project));
+        }
         match s {
             "bbmri" => Ok(Project::Bbmri),
             "dktk" => Ok(Project::Dktk),
@@ -45,9 +84,18 @@ impl FromStr for Project {
     }
 }
 
+/// Splits criteria of other projects, namespaced like dktk:diagnosis, into their project and key
+pub fn namespaced(key: &str) -> Result<Option<(Project, &str)>, FocusError> {
+    match key.split_once(':') {
+        Some((namespace, key)) => Ok(Some((namespace.parse()?, key))),
+        None => Ok(None),
+    }
+}
+
 impl Project {
     pub fn get_code_lists(&self) -> &'static HashMap<&'static str, &'static str> {
         match self {
+            Project::Custom(project) => &project.code_lists,
             Project::Bbmri => &bbmri::CODE_LISTS,
             Project::Dktk => &dktk::CODE_LISTS,
             Project::Cce => &cce::CODE_LISTS,
@@ -60,6 +108,7 @@ impl Project {
 
     pub fn get_observation_loinc_codes(&self) -> &'static HashMap<&'static str, &'static str> {
         match self {
+            Project::Custom(project) => &project.observation_loinc_codes,
             Project::Bbmri => &bbmri::OBSERVATION_LOINC_CODES,
             Project::Dktk => &dktk::OBSERVATION_LOINC_CODES,
             Project::Cce => &cce::OBSERVATION_LOINC_CODES,
@@ -70,20 +119,20 @@ impl Project {
         }
     }
 
-    pub fn get_sample_type_workarounds(&self) -> &'static HashMap<&'static str, Vec<&'static str>> {
+    /// Values standing for several values in the data, per criterion and value
+    pub fn get_value_mappings(
+        &self,
+    ) -> &'static HashMap<&'static str, HashMap<&'static str, Vec<&'static str>>> {
         match self {
-            Project::Bbmri => &bbmri::SAMPLE_TYPE_WORKAROUNDS,
-            Project::Dktk => &dktk::SAMPLE_TYPE_WORKAROUNDS,
-            Project::Cce => &cce::SAMPLE_TYPE_WORKAROUNDS,
-            Project::Dhki => &dhki::SAMPLE_TYPE_WORKAROUNDS,
-            Project::Nngm => &nngm::SAMPLE_TYPE_WORKAROUNDS,
-            Project::Itcc => &itcc::SAMPLE_TYPE_WORKAROUNDS,
-            Project::Pscc => &pscc::SAMPLE_TYPE_WORKAROUNDS,
+            Project::Custom(project) => &project.value_mappings,
+            Project::Bbmri => &bbmri::VALUE_MAPPINGS,
+            _ => &NO_VALUE_MAPPINGS,
         }
     }
 
     pub fn get_criterion_code_lists(&self) -> &'static HashMap<&'static str, Vec<&'static str>> {
         match self {
+            Project::Custom(project) => &project.criterion_code_lists,
             Project::Bbmri => &bbmri::CRITERION_CODE_LISTS,
             Project::Dktk => &dktk::CRITERION_CODE_LISTS,
             Project::Cce => &cce::CRITERION_CODE_LISTS,
@@ -98,6 +147,7 @@ impl Project {
         &self,
     ) -> &'static HashMap<(&'static str, CriterionRole), &'static str> {
         match self {
+            Project::Custom(project) => &project.cql_snippets,
             Project::Bbmri => &bbmri::CQL_SNIPPETS,
             Project::Dktk => &dktk::CQL_SNIPPETS,
             Project::Cce => &cce::CQL_SNIPPETS,
@@ -108,8 +158,39 @@ impl Project {
         }
     }
 
+    pub fn get_criterion_units(&self) -> &'static HashMap<&'static str, &'static str> {
+        match self {
+            Project::Custom(project) => &project.criterion_units,
+            Project::Bbmri => &bbmri::CRITERION_UNITS,
+            _ => &NO_CRITERION_UNITS,
+        }
+    }
+
+    /// Sources of the resources criteria can be grouped by, so that they all have to hold for the same resource
+    pub fn get_entity_sources(&self) -> &'static HashMap<&'static str, &'static str> {
+        match self {
+            Project::Custom(project) => &project.entity_sources,
+            Project::Bbmri => &bbmri::ENTITY_SOURCES,
+            Project::Dktk => &dktk::ENTITY_SOURCES,
+            _ => &NO_ENTITY_SOURCES,
+        }
+    }
+
+    /// Snippets of the criteria that can be grouped by an entity, keyed by entity and criterion
+    pub fn get_entity_snippets(
+        &self,
+    ) -> &'static HashMap<(&'static str, &'static str), &'static str> {
+        match self {
+            Project::Custom(project) => &project.entity_snippets,
+            Project::Bbmri => &bbmri::ENTITY_SNIPPETS,
+            Project::Dktk => &dktk::ENTITY_SNIPPETS,
+            _ => &NO_ENTITY_SNIPPETS,
+        }
+    }
+
     pub fn get_mandatory_code_lists(&self) -> &'static IndexSet<&'static str> {
         match self {
+            Project::Custom(project) => &project.mandatory_code_lists,
             Project::Bbmri => &bbmri::MANDATORY_CODE_LISTS,
             Project::Dktk => &dktk::MANDATORY_CODE_LISTS,
             Project::Cce => &cce::MANDATORY_CODE_LISTS,
@@ -122,6 +203,7 @@ impl Project {
 
     pub fn get_cql_template(&self) -> &'static str {
         match self {
+            Project::Custom(project) => project.cql_template,
             Project::Bbmri => include_str!("bbmri/template.cql"),
             Project::Dktk => include_str!("dktk/template.cql"),
             Project::Cce => include_str!("cce/template.cql"),
@@ -134,6 +216,7 @@ impl Project {
 
     pub fn get_body(&self) -> &'static str {
         match self {
+            Project::Custom(project) => project.body,
             Project::Bbmri => include_str!("bbmri/body.json"),
             Project::Dktk => include_str!("dktk/body.json"),
             Project::Cce => include_str!("cce/body.json"),
@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{LazyLock, OnceLock},
+};
+
+use indexmap::IndexSet;
+use serde::Deserialize;
+
+use crate::ast;
+use crate::errors::FocusError;
+use crate::projects::{self, Project};
+
+/// Hierarchies loaded at startup, keyed by code system URL
+static HIERARCHIES: OnceLock<HashMap<String, Hierarchy>> = OnceLock::new();
+
+static NO_HIERARCHIES: LazyLock<HashMap<String, Hierarchy>> = LazyLock::new(HashMap::new);
+
+/// Parent-child relations of the codes of a code system, e.g. C50 to C50.0 to C50.9 in ICD-10
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hierarchy {
+    system: String,
+    children: HashMap<String, Vec<String>>,
+}
+
+impl Hierarchy {
+    /// All codes below the code, children before grandchildren
+    fn descendants(&self, code: &str) -> Vec<&str> {
+        let mut descendants = Vec::new();
+        let mut seen = HashSet::from([code]);
+        let mut index = 0;
+        let mut current = code;
+        loop {
+            for child in self.children.get(current).into_iter().flatten() {
+                if seen.insert(child.as_str()) {
+                    descendants.push(child.as_str());
+                }
+            }
+            let Some(next) = descendants.get(index) else {
+                return descendants;
+            };
+            current = *next;
+            index += 1;
+        }
+    }
+}
+
+/// Reads all JSON hierarchy files in `dir`, each giving the `system` it describes and the `children` of its codes
+fn read_hierarchies(dir: &Path) -> Result<HashMap<String, Hierarchy>, FocusError> {
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        FocusError::InvalidTerminology(format!(
+            "Unable to read terminology directory {}: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+
+    let mut hierarchies = HashMap::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| FocusError::InvalidTerminology(e.to_string()))?
+            .path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let hierarchy: Hierarchy = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| serde_json::from_str(&content).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                FocusError::InvalidTerminology(format!(
+                    "Unable to read hierarchy {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+        hierarchies.insert(hierarchy.system.clone(), hierarchy);
+    }
+    Ok(hierarchies)
+}
+
+/// Loads the hierarchies in `dir` for descendant matching, returns the code systems they describe
+pub fn load_hierarchies(dir: &Path) -> Result<Vec<String>, FocusError> {
+    let hierarchies = read_hierarchies(dir)?;
+    let mut systems: Vec<String> = hierarchies.keys().cloned().collect();
+    systems.sort();
+    HIERARCHIES.set(hierarchies).map_err(|_| {
+        FocusError::InvalidTerminology("Hierarchies can only be loaded once".into())
+    })?;
+    Ok(systems)
+}
+
+/// The loaded hierarchies, none if no terminology directory is configured
+pub fn hierarchies() -> &'static HashMap<String, Hierarchy> {
+    HIERARCHIES.get().unwrap_or(&NO_HIERARCHIES)
+}
+
+/// Adds the descendants of the codes of all conditions asking for them, as far as the code systems of the criteria have hierarchies
+pub fn expand(
+    operation: ast::Operation,
+    project: &Project,
+    hierarchies: &HashMap<String, Hierarchy>,
+) -> ast::Operation {
+    ast::Operation {
+        operand: operation.operand,
+        children: operation
+            .children
+            .into_iter()
+            .map(|child| match child {
+                ast::Child::Operation(operation) => {
+                    ast::Child::Operation(expand(operation, project, hierarchies))
+                }
+                ast::Child::Condition(condition) => {
+                    ast::Child::Condition(expand_condition(condition, project, hierarchies))
+                }
+                ast::Child::Temporal(temporal) => ast::Child::Temporal(ast::Temporal {
+                    anchor: expand_condition(temporal.anchor, project, hierarchies),
+                    event: expand_condition(temporal.event, project, hierarchies),
+                    ..temporal
+                }),
+                ast::Child::Entity(entity) => ast::Child::Entity(ast::Entity {
+                    children: entity
+                        .children
+                        .into_iter()
+                        .map(|condition| expand_condition(condition, project, hierarchies))
+                        .collect(),
+                    ..entity
+                }),
+            })
+            .collect(),
+    }
+}
+
+fn expand_condition(
+    condition: ast::Condition,
+    project: &Project,
+    hierarchies: &HashMap<String, Hierarchy>,
+) -> ast::Condition {
+    if !condition.descendants {
+        return condition;
+    }
+    let namespaced = projects::namespaced(&condition.key).ok().flatten();
+    let (project, key) = match &namespaced {
+        Some((namespace_project, key)) => (namespace_project, *key),
+        None => (project, condition.key.as_str()),
+    };
+    let criterion_hierarchies: Vec<&Hierarchy> = project
+        .get_criterion_code_lists()
+        .get(key)
+        .into_iter()
+        .flatten()
+        .filter_map(|code_list| project.get_code_lists().get(code_list))
+        .filter_map(|system| hierarchies.get(*system))
+        .collect();
+
+    let codes = match (&condition.type_, &condition.value) {
+        (ast::ConditionType::Equals, ast::ConditionValue::String(code)) => vec![code.clone()],
+        (ast::ConditionType::In, ast::ConditionValue::StringArray(codes)) => codes.clone(),
+        // validation rejects descendants for other operators
+        _ => return condition,
+    };
+    let mut expanded: IndexSet<String> = IndexSet::new();
+    for code in codes {
+        expanded.insert(code.clone());
+        for hierarchy in &criterion_hierarchies {
+            expanded.extend(hierarchy.descendants(&code).into_iter().map(String::from));
+        }
+    }
+
+    if expanded.len() == 1 {
+        return condition;
+    }
+    ast::Condition {
+        type_: ast::ConditionType::In,
+        value: ast::ConditionValue::StringArray(expanded.into_iter().collect()),
+        ..condition
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BREAST_CANCER: &str = r#"{"ast":{"operand":"OR","children":[{"key":"diagnosis","type":"EQUALS","value":"C50","descendants":true},{"key":"diagnosis","type":"IN","value":["C50","C61"]},{"key":"gender","type":"EQUALS","value":"female","descendants":true}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_expand() {
+        let hierarchies = read_hierarchies(Path::new("resources/test/terminology")).unwrap();
+        let ast: ast::Ast = serde_json::from_str(BREAST_CANCER).unwrap();
+
+        let expanded = expand(ast.ast, &Project::Dktk, &hierarchies);
+        let values: Vec<String> = expanded
+            .children
+            .iter()
+            .map(|child| match child {
+                ast::Child::Condition(condition) => {
+                    serde_json::to_string(&condition.value).unwrap()
+                }
+                _ => unreachable!(),
+            })
+            .collect();
+
+        pretty_assertions::assert_eq!(
+            values,
+            vec![
+                r#"["C50","C50.0","C50.1","C50.9","C50.91"]"#,
+                r#"["C50","C61"]"#,
+                r#""female""#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use crate::errors::FocusError;
+use crate::projects::{check_projects, load_projects, names};
+
+#[derive(clap::Args, Debug)]
+pub struct LintProjectsArgs {
+    /// Comma separated projects to check, all if not given
+    #[clap(long, value_parser, value_delimiter = ',')]
+    projects: Option<Vec<String>>,
+
+    /// Directory with project definitions to check along with the compiled-in ones, see PROJECTS_DIR
+    #[clap(long, env, value_parser)]
+    projects_dir: Option<PathBuf>,
+}
+
+/// Prints the problems found in the project definitions, fails if there are any
+pub fn run(args: LintProjectsArgs) -> Result<(), FocusError> {
+    if let Some(dir) = &args.projects_dir {
+        load_projects(dir)?;
+    }
+    let projects = args.projects.unwrap_or_else(names);
+
+    let problems = check_projects(projects.iter().map(String::as_str))?;
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("No problems found in {}", projects.join(", "));
+        Ok(())
+    } else {
+        Err(FocusError::InvalidProjectDefinition(format!(
+            "{} problems found",
+            problems.len()
+        )))
+    }
+}
@@ -0,0 +1,43 @@
+use std::process::ExitCode;
+
+use clap::{CommandFactory, Parser};
+
+mod lint_projects;
+mod simulate_obfuscation;
+mod translate;
+
+/// Offline tools that run instead of the Focus service and need neither Beam nor an endpoint
+#[derive(Parser, Debug)]
+#[clap(name("🔭 Focus"), version)]
+enum Tool {
+    /// Obfuscates a MeasureReport many times and reports how much the obfuscation distorts its counts
+    SimulateObfuscation(simulate_obfuscation::SimulateObfuscationArgs),
+    /// Translates an AST to CQL, EUCAIM SQL or a EUCAIM API URL, for a fast feedback loop when changing project snippets
+    Translate(translate::TranslateArgs),
+    /// Checks project definitions for placeholders, code systems and criteria that would make the generated CQL invalid
+    LintProjects(lint_projects::LintProjectsArgs),
+}
+
+/// Runs the tool named by the first command line argument, if any, and returns its exit code
+pub fn run_if_requested() -> Option<ExitCode> {
+    let requested = std::env::args().nth(1)?;
+    if !Tool::command()
+        .get_subcommands()
+        .any(|tool| tool.get_name() == requested)
+    {
+        return None;
+    }
+
+    let result = match Tool::parse() {
+        Tool::SimulateObfuscation(args) => simulate_obfuscation::run(args),
+        Tool::Translate(args) => translate::run(args),
+        Tool::LintProjects(args) => lint_projects::run(args),
+    };
+    Some(match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    })
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use laplace_rs::ObfCache;
+
+use crate::errors::FocusError;
+use crate::mr::MeasureReport;
+use crate::obfuscation::{read_project_params, ObfuscationArgs, ObfuscationParams};
+use crate::util::obfuscate_counts_mr;
+
+/// Non-zero counts below this are small enough for single patients to be singled out
+const SMALL_COUNT: u64 = 10;
+
+#[derive(clap::Args, Debug)]
+pub struct SimulateObfuscationArgs {
+    /// Path to a MeasureReport as returned by Blaze, e.g. resources/test/measure_report_bbmri.json
+    #[clap(value_parser)]
+    measure_report: PathBuf,
+
+    /// How many times the MeasureReport is obfuscated
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value = "1000")]
+    iterations: u64,
+
+    /// Simulate with the parameters of this project in the project obfuscation file
+    #[clap(long, value_parser, requires = "project_obfuscation_file")]
+    project: Option<String>,
+
+    /// The path to a JSON file with obfuscation parameters per project
+    #[clap(long, env, value_parser)]
+    project_obfuscation_file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    obfuscation: ObfuscationArgs,
+}
+
+/// A single count in a MeasureReport
+#[derive(Debug, Clone, PartialEq)]
+struct Cell {
+    /// Group, stratifier and stratum the count belongs to
+    name: String,
+    count: u64,
+}
+
+#[derive(Debug, PartialEq)]
+struct CellReport {
+    cell: Cell,
+    mean_absolute_error: f64,
+    /// 5th, 50th and 95th percentile of the released count minus the true count
+    error_percentiles: [i64; 3],
+    /// Share of releases hiding a non-zero count behind a zero
+    suppression_rate: f64,
+    /// Share of releases giving away a small count exactly, `None` for counts that are not small
+    disclosure_rate: Option<f64>,
+}
+
+impl CellReport {
+    fn new(cell: Cell, released: &[u64]) -> Self {
+        let n = released.len() as f64;
+        let mut errors: Vec<i64> = released
+            .iter()
+            .map(|&value| value as i64 - cell.count as i64)
+            .collect();
+        errors.sort_unstable();
+        let percentile = |q: f64| errors[((errors.len() - 1) as f64 * q).round() as usize];
+
+        let mean_absolute_error = errors.iter().map(|e| e.unsigned_abs() as f64).sum::<f64>() / n;
+        let error_percentiles = [percentile(0.05), percentile(0.5), percentile(0.95)];
+        let suppression_rate = if cell.count > 0 {
+            released.iter().filter(|&&value| value == 0).count() as f64 / n
+        } else {
+            0.
+        };
+        let disclosure_rate = (1..SMALL_COUNT).contains(&cell.count).then(|| {
+            released
+                .iter()
+                .filter(|&&value| value == cell.count)
+                .count() as f64
+                / n
+        });
+
+        CellReport {
+            cell,
+            mean_absolute_error,
+            error_percentiles,
+            suppression_rate,
+            disclosure_rate,
+        }
+    }
+}
+
+fn cells(measure_report: &MeasureReport) -> Vec<Cell> {
+    let mut cells = Vec::new();
+    for group in &measure_report.group {
+        for population in &group.population {
+            cells.push(Cell {
+                name: group.code.text.clone(),
+                count: population.count,
+            });
+        }
+        for stratifier in &group.stratifier {
+            let stratifier_name = stratifier
+                .code
+                .first()
+                .map(|code| code.text.as_str())
+                .unwrap_or_default();
+            for stratum in stratifier.stratum.iter().flatten() {
+                for population in &stratum.population {
+                    cells.push(Cell {
+                        name: format!(
+                            "{} / {} / {}",
+                            group.code.text, stratifier_name, stratum.value.text
+                        ),
+                        count: population.count,
+                    });
+                }
+            }
+        }
+    }
+    cells
+}
+
+fn simulate(
+    measure_report: &str,
+    params: &ObfuscationParams,
+    iterations: u64,
+) -> Result<Vec<CellReport>, FocusError> {
+    let original = cells(&serde_json::from_str(measure_report)?);
+    let mut released: Vec<Vec<u64>> = vec![Vec::new(); original.len()];
+
+    for _ in 0..iterations {
+        // every iteration stands for a fresh Focus instance, a shared cache would just repeat the first release
+        let mut obf_cache = ObfCache {
+            cache: Default::default(),
+        };
+        let obfuscated: MeasureReport = serde_json::from_str(&obfuscate_counts_mr(
+            measure_report,
+            &mut obf_cache,
+            params,
+        )?)?;
+        for (values, cell) in released.iter_mut().zip(cells(&obfuscated)) {
+            values.push(cell.count);
+        }
+    }
+
+    Ok(original
+        .into_iter()
+        .zip(released)
+        .map(|(cell, values)| CellReport::new(cell, &values))
+        .collect())
+}
+
+/// Expected share of small counts given away exactly, averaged over all small counts
+fn reidentification_risk(reports: &[CellReport]) -> Option<f64> {
+    let rates: Vec<f64> = reports.iter().filter_map(|r| r.disclosure_rate).collect();
+    (!rates.is_empty()).then(|| rates.iter().sum::<f64>() / rates.len() as f64)
+}
+
+/// Parameters of the project, which has to be in the project obfuscation file so the report isn't made for other parameters
+fn project_params(
+    mut projects: HashMap<String, ObfuscationParams>,
+    project: &str,
+    path: &Path,
+) -> Result<ObfuscationParams, FocusError> {
+    projects.remove(project).ok_or_else(|| {
+        FocusError::ConfigurationError(format!(
+            "Project {} has no obfuscation parameters in {}",
+            project,
+            path.display()
+        ))
+    })
+}
+
+pub fn run(args: SimulateObfuscationArgs) -> Result<(), FocusError> {
+    let global = args.obfuscation.into_params()?;
+    let params = match (args.project, args.project_obfuscation_file) {
+        (Some(project), Some(path)) => {
+            project_params(read_project_params(&path, &global)?, &project, &path)?
+        }
+        _ => global,
+    };
+    let measure_report = std::fs::read_to_string(&args.measure_report).map_err(|e| {
+        FocusError::ParsingError(format!(
+            "Unable to read MeasureReport {}: {}",
+            args.measure_report.display(),
+            e
+        ))
+    })?;
+
+    let reports = simulate(&measure_report, &params, args.iterations)?;
+
+    println!("Obfuscation parameters: {:?}", params);
+    println!("Iterations: {}", args.iterations);
+    println!();
+    println!(
+        "{:<60} {:>8} {:>10} {:>20} {:>11} {:>10}",
+        "count", "true", "mean |err|", "error p5/p50/p95", "suppressed", "disclosed"
+    );
+    for report in &reports {
+        let [p5, p50, p95] = report.error_percentiles;
+        println!(
+            "{:<60} {:>8} {:>10.2} {:>20} {:>10.1}% {:>10}",
+            report.cell.name,
+            report.cell.count,
+            report.mean_absolute_error,
+            format!("{}/{}/{}", p5, p50, p95),
+            report.suppression_rate * 100.,
+            report
+                .disclosure_rate
+                .map(|rate| format!("{:.1}%", rate * 100.))
+                .unwrap_or_else(|| "-".into()),
+        );
+    }
+    println!();
+    match reidentification_risk(&reports) {
+        Some(risk) => println!(
+            "Expected re-identification risk: {:.1}% of counts between 1 and {} are released exactly",
+            risk * 100.,
+            SMALL_COUNT - 1
+        ),
+        None => println!(
+            "Expected re-identification risk: none, there are no counts between 1 and {}",
+            SMALL_COUNT - 1
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::obfuscation::{Below10Mode, ObfuscationPreset};
+
+    const EXAMPLE_MEASURE_REPORT_BBMRI: &str =
+        include_str!("../../resources/test/measure_report_bbmri.json");
+
+    #[test]
+    fn test_simulate() {
+        let params = ObfuscationPreset::Default.params();
+        let reports = simulate(EXAMPLE_MEASURE_REPORT_BBMRI, &params, 20).unwrap();
+
+        pretty_assertions::assert_eq!(
+            reports[0].cell,
+            Cell {
+                name: "patients".into(),
+                count: 74
+            }
+        );
+        pretty_assertions::assert_eq!(
+            reports[4].cell,
+            Cell {
+                name: "patients / Age / 50".into(),
+                count: 4
+            }
+        );
+        // small counts are released as ten, so they are neither suppressed nor given away
+        pretty_assertions::assert_eq!(reports[4].error_percentiles, [6, 6, 6]);
+        pretty_assertions::assert_eq!(reports[4].suppression_rate, 0.);
+        pretty_assertions::assert_eq!(reports[4].disclosure_rate, Some(0.));
+        pretty_assertions::assert_eq!(reports[0].disclosure_rate, None);
+        pretty_assertions::assert_eq!(reidentification_risk(&reports), Some(0.));
+    }
+
+    #[test]
+    fn test_simulate_zero_mode() {
+        let params = ObfuscationParams {
+            below_10_mode: Below10Mode::Zero,
+            ..ObfuscationPreset::Default.params()
+        };
+        let reports = simulate(EXAMPLE_MEASURE_REPORT_BBMRI, &params, 20).unwrap();
+
+        pretty_assertions::assert_eq!(reports[4].suppression_rate, 1.);
+    }
+
+    #[test]
+    fn test_project_params() {
+        let path = Path::new("project_obfuscation.json");
+        let projects = HashMap::from([("dktk".to_string(), ObfuscationPreset::Strict.params())]);
+
+        assert!(project_params(projects.clone(), "dktk", path).is_ok());
+        let error = project_params(projects, "bbmri", path).unwrap_err();
+        assert!(error.to_string().contains("bbmri"));
+    }
+}
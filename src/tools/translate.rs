@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+
+use reqwest::Url;
+
+use crate::ast;
+use crate::config::EndpointType;
+use crate::errors::FocusError;
+use crate::explain::{explain, Explanation};
+use crate::projects::load_projects;
+use crate::terminology::load_hierarchies;
+
+/// What an AST is translated to
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Target {
+    /// CQL library and Measure for Blaze
+    Cql,
+    /// SQL for EUCAIM SQL endpoints
+    #[cfg(feature = "query-sql")]
+    EucaimSql,
+    /// Query URL of the EUCAIM API
+    EucaimUrl,
+}
+
+impl From<Target> for EndpointType {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Cql => EndpointType::Blaze,
+            #[cfg(feature = "query-sql")]
+            Target::EucaimSql => EndpointType::EucaimSql,
+            Target::EucaimUrl => EndpointType::EucaimApi,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TranslateArgs {
+    /// Path to a JSON AST as sent by Lens
+    #[clap(long, value_parser)]
+    ast: PathBuf,
+
+    /// Project whose snippets the CQL is generated from
+    #[clap(long, value_parser, default_value = "bbmri")]
+    project: String,
+
+    #[clap(long, value_enum, default_value = "cql")]
+    target: Target,
+
+    /// Comma separated stratifiers to compute, all of the project if not given
+    #[clap(long, value_parser, value_delimiter = ',')]
+    stratifiers: Option<Vec<String>>,
+
+    /// Directory with project definitions to use instead of the compiled-in ones, see PROJECTS_DIR
+    #[clap(long, env, value_parser)]
+    projects_dir: Option<PathBuf>,
+
+    /// Directory with code hierarchies for descendant matching, see TERMINOLOGY_DIR
+    #[clap(long, env, value_parser)]
+    terminology_dir: Option<PathBuf>,
+
+    /// Base URL of the EUCAIM API the query URL is built for
+    #[clap(long, value_parser, default_value = "http://localhost:8080/search")]
+    endpoint_url: Url,
+}
+
+/// Prints what Focus would send to its endpoint for an AST, without needing Beam or the endpoint
+pub fn run(args: TranslateArgs) -> Result<(), FocusError> {
+    let ast = std::fs::read_to_string(&args.ast).map_err(|e| {
+        FocusError::ParsingError(format!("Unable to read AST {}: {}", args.ast.display(), e))
+    })?;
+    let ast: ast::Ast = serde_json::from_str(&ast)?;
+    if let Some(dir) = &args.projects_dir {
+        load_projects(dir)?;
+    }
+    if let Some(dir) = &args.terminology_dir {
+        load_hierarchies(dir)?;
+    }
+
+    match explain(
+        ast,
+        &args.project,
+        args.stratifiers.as_deref(),
+        args.target.into(),
+        args.endpoint_url,
+    )? {
+        Explanation::Cql { library, measure } => {
+            println!("{}", library);
+            println!();
+            println!("{}", serde_json::to_string_pretty(&measure)?);
+        }
+        Explanation::Sql { query } => println!("{}", query),
+        Explanation::Url { url } => println!("{}", url),
+        Explanation::Ast { ast } => println!("{}", serde_json::to_string_pretty(&ast)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use crate::errors::FocusError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dimension {
+    Mass,
+    Length,
+    MassPerArea, // e.g. BMI
+}
+
+/// UCUM codes of the units Focus can convert between, with their dimension and how many base units (kg, m, kg/m2) they are
+static UNITS: LazyLock<HashMap<&'static str, (Dimension, f64)>> = LazyLock::new(|| {
+    HashMap::from([
+        ("kg", (Dimension::Mass, 1.)),
+        ("g", (Dimension::Mass, 1e-3)),
+        ("mg", (Dimension::Mass, 1e-6)),
+        ("[lb_av]", (Dimension::Mass, 0.45359237)),
+        ("[oz_av]", (Dimension::Mass, 0.028349523125)),
+        ("m", (Dimension::Length, 1.)),
+        ("cm", (Dimension::Length, 1e-2)),
+        ("mm", (Dimension::Length, 1e-3)),
+        ("[in_i]", (Dimension::Length, 0.0254)),
+        ("[ft_i]", (Dimension::Length, 0.3048)),
+        ("kg/m2", (Dimension::MassPerArea, 1.)),
+    ])
+});
+
+/// Converts a value from one UCUM unit to another of the same dimension
+pub fn convert(value: f64, from: &str, to: &str) -> Result<f64, FocusError> {
+    let lookup = |unit: &str| {
+        UNITS
+            .get(unit)
+            .ok_or_else(|| FocusError::AstInvalidUnit(format!("Unknown unit {}", unit)))
+    };
+    let (from_dimension, from_factor) = lookup(from)?;
+    let (to_dimension, to_factor) = lookup(to)?;
+
+    if from_dimension != to_dimension {
+        return Err(FocusError::AstInvalidUnit(format!(
+            "{} can't be converted to {}",
+            from, to
+        )));
+    }
+    Ok(value * from_factor / to_factor)
+}
+
+pub fn is_known(unit: &str) -> bool {
+    UNITS.contains_key(unit)
+}
+
+/// A CQL quantity literal, the unit has to be one of the known ones, so no CQL injection is possible.
+/// Values are rounded to 6 decimals to hide floating point noise from conversions
+pub fn cql_quantity(value: f64, unit: &str) -> String {
+    format!("{} '{}'", (value * 1e6).round() / 1e6, unit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        pretty_assertions::assert_eq!(convert(70000., "g", "kg").unwrap(), 70.);
+        assert!((convert(100., "[lb_av]", "kg").unwrap() - 45.359237).abs() < 1e-9);
+        pretty_assertions::assert_eq!(convert(25., "kg/m2", "kg/m2").unwrap(), 25.);
+
+        assert!(matches!(
+            convert(70., "kg", "cm"),
+            Err(FocusError::AstInvalidUnit(_))
+        ));
+        assert!(matches!(
+            convert(70., "stone", "kg"),
+            Err(FocusError::AstInvalidUnit(_))
+        ));
+    }
+}
@@ -1,5 +1,7 @@
+use crate::cql_syntax;
 use crate::errors::FocusError;
 use crate::mr;
+use crate::obfuscation::ObfuscationParams;
 use base64::engine::general_purpose;
 use base64::Engine as _;
 use laplace_rs::{get_from_cache_or_privatize, Bin, ObfCache, ObfuscateBelow10Mode};
@@ -23,40 +25,30 @@ pub(crate) fn base64_decode(data: impl AsRef<[u8]>) -> Result<Vec<u8>, FocusErro
 include!(concat!(env!("OUT_DIR"), "/replace_map.rs"));
 
 pub(crate) fn replace_cql(decoded_library: impl Into<String>) -> String {
-    let mut decoded_library = decoded_library.into();
-
-    for (key, value) in REPLACE_MAP.iter() {
-        decoded_library = decoded_library.replace(key, &value[..]);
-    }
-    decoded_library
+    cql_syntax::replace_placeholders(&decoded_library.into(), &REPLACE_MAP)
 }
 
-pub(crate) fn is_cql_tampered_with(decoded_library: impl Into<String>) -> bool {
-    let decoded_library = decoded_library.into();
-    decoded_library.contains("define")
+/// Fails if a library with placeholders contains more than its header, the placeholders and the expressions they leave open
+pub(crate) fn check_cql_tampering(decoded_library: &str) -> Result<(), FocusError> {
+    let errors = cql_syntax::check_tampering(decoded_library, &REPLACE_MAP);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(FocusError::CQLTemperedWithError(
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; "),
+    ))
 }
 
-#[allow(clippy::too_many_arguments)]
 pub fn obfuscate_counts_mr(
     json_str: &str,
     obf_cache: &mut ObfCache,
-    obfuscate_zero: bool,
-    obfuscate_below_10_mode: usize,
-    delta_patient: f64,
-    delta_specimen: f64,
-    delta_diagnosis: f64,
-    delta_procedures: f64,
-    delta_medication_statements: f64,
-    delta_histo: f64,
-    epsilon: f64,
-    rounding_step: usize,
+    params: &ObfuscationParams,
 ) -> Result<String, FocusError> {
-    let obf_10: ObfuscateBelow10Mode = match obfuscate_below_10_mode {
-        0 => ObfuscateBelow10Mode::Zero,
-        1 => ObfuscateBelow10Mode::Ten,
-        2 => ObfuscateBelow10Mode::Obfuscate,
-        _ => ObfuscateBelow10Mode::Obfuscate,
-    };
+    let obf_10: ObfuscateBelow10Mode = params.below_10_mode.into();
     let mut measure_report: mr::MeasureReport = serde_json::from_str(json_str)
         .map_err(|e| FocusError::DeserializationError(format!(r#"{}. Is obfuscation turned on when it shouldn't be? Is the metadata in the task formatted correctly, like this {{"project": "name"}}? Are there any other projects stated in the projects_no_obfuscation parameter in the bridgehead?"#, e)))?;
     for g in &mut measure_report.group {
@@ -65,155 +57,155 @@ pub fn obfuscate_counts_mr(
                 // Prism used "patient" for catalogue, Lens uses "patients"
                 obfuscate_population(
                     &mut g.population,
-                    delta_patient,
-                    epsilon,
+                    params.delta_patient,
+                    params.epsilon,
                     1,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
                 obfuscate_stratifier(
                     &mut g.stratifier,
-                    delta_patient,
-                    epsilon,
+                    params.delta_patient,
+                    params.epsilon,
                     2,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
             }
             "diagnosis" => {
                 obfuscate_population(
                     &mut g.population,
-                    delta_diagnosis,
-                    epsilon,
+                    params.delta_diagnosis,
+                    params.epsilon,
                     1,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
                 obfuscate_stratifier(
                     &mut g.stratifier,
-                    delta_diagnosis,
-                    epsilon,
+                    params.delta_diagnosis,
+                    params.epsilon,
                     2,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
             }
             "specimen" => {
                 obfuscate_population(
                     &mut g.population,
-                    delta_specimen,
-                    epsilon,
+                    params.delta_specimen,
+                    params.epsilon,
                     1,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
                 obfuscate_stratifier(
                     &mut g.stratifier,
-                    delta_specimen,
-                    epsilon,
+                    params.delta_specimen,
+                    params.epsilon,
                     2,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
             }
             "procedures" => {
                 obfuscate_population(
                     &mut g.population,
-                    delta_procedures,
-                    epsilon,
+                    params.delta_procedures,
+                    params.epsilon,
                     1,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
                 obfuscate_stratifier(
                     &mut g.stratifier,
-                    delta_procedures,
-                    epsilon,
+                    params.delta_procedures,
+                    params.epsilon,
                     2,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
             }
             "medicationStatements" => {
                 obfuscate_population(
                     &mut g.population,
-                    delta_medication_statements,
-                    epsilon,
+                    params.delta_medication_statements,
+                    params.epsilon,
                     1,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
                 obfuscate_stratifier(
                     &mut g.stratifier,
-                    delta_medication_statements,
-                    epsilon,
+                    params.delta_medication_statements,
+                    params.epsilon,
                     2,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
             }
             "MolecularMarker" => {
                 obfuscate_population(
                     &mut g.population,
-                    delta_histo,
-                    epsilon,
+                    params.delta_histo,
+                    params.epsilon,
                     1,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
                 obfuscate_stratifier(
                     &mut g.stratifier,
-                    delta_histo,
-                    epsilon,
+                    params.delta_histo,
+                    params.epsilon,
                     2,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
             }
             "Encounters" => {
                 obfuscate_population(
                     &mut g.population,
-                    delta_histo,
-                    epsilon,
+                    params.delta_histo,
+                    params.epsilon,
                     1,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
                 obfuscate_stratifier(
                     &mut g.stratifier,
-                    delta_histo,
-                    epsilon,
+                    params.delta_histo,
+                    params.epsilon,
                     2,
                     obf_cache,
-                    obfuscate_zero,
+                    params.obfuscate_zero,
                     obf_10.clone(),
-                    rounding_step,
+                    params.rounding_step,
                 )?;
             }
             strat => {
@@ -295,6 +287,7 @@ fn obfuscate_stratifier(
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::obfuscation::Below10Mode;
     use serde_json::json;
 
     const QUERY_BBMRI_PLACEHOLDERS: &str =
@@ -309,14 +302,18 @@ mod test {
     const EXAMPLE_MEASURE_REPORT_EXLIQUID: &str =
         include_str!("../resources/test/measure_report_exliquid.json");
 
-    const DELTA_PATIENT: f64 = 1.;
-    const DELTA_SPECIMEN: f64 = 20.;
-    const DELTA_DIAGNOSIS: f64 = 3.;
-    const DELTA_PROCEDURES: f64 = 1.7;
-    const DELTA_MEDICATION_STATEMENTS: f64 = 2.1;
-    const DELTA_HISTO: f64 = 20.;
-    const EPSILON: f64 = 0.1;
-    const ROUNDING_STEP: usize = 10;
+    const PARAMS: ObfuscationParams = ObfuscationParams {
+        obfuscate_zero: false,
+        below_10_mode: Below10Mode::Ten,
+        delta_patient: 1.,
+        delta_specimen: 20.,
+        delta_diagnosis: 3.,
+        delta_procedures: 1.7,
+        delta_medication_statements: 2.1,
+        delta_histo: 20.,
+        epsilon: 0.1,
+        rounding_step: 10,
+    };
 
     #[test]
     fn test_get_json_field_success() {
@@ -376,16 +373,36 @@ mod test {
     }
 
     #[test]
-    fn test_is_cql_tampered_with_true() {
+    fn test_cql_tampered_with() {
         let decoded_library =
             "define Gender:\n if (Patient.gender is null) then 'unknown' else Patient.gender \n";
-        assert!(is_cql_tampered_with(decoded_library));
+        assert!(check_cql_tampering(decoded_library).is_err());
+
+        for decoded_library in [
+            "context Patient\nBBMRI_STRAT_GENDER_STRATIFIER true",
+            "context Patient\nBBMRI_STRAT_DEF_IN_INITIAL_POPULATION\ntrue define Gender: 'male'",
+            "context Patient\nparameter Secret String",
+            "context Patient\nBBMRI_STRAT_GENDER_STRATIFIER\nusing QDM",
+        ] {
+            assert!(
+                check_cql_tampering(decoded_library).is_err(),
+                "{}",
+                decoded_library
+            );
+        }
     }
 
     #[test]
-    fn test_is_cql_tampered_with_false() {
+    fn test_cql_not_tampered_with() {
         let decoded_library = "context Patient\nBBMRI_STRAT_GENDER_STRATIFIER";
-        assert!(!is_cql_tampered_with(decoded_library));
+        assert!(check_cql_tampering(decoded_library).is_ok());
+
+        assert!(check_cql_tampering(QUERY_BBMRI_PLACEHOLDERS).is_ok());
+
+        // define is only a keyword outside of strings
+        let decoded_library =
+            "context Patient\nBBMRI_STRAT_DEF_IN_INITIAL_POPULATION\nPatient.gender != 'undefined'";
+        assert!(check_cql_tampering(decoded_library).is_ok());
     }
 
     #[test]
@@ -404,6 +421,9 @@ mod test {
         let decoded_library = "INVALID_KEY";
         let expected_result = "INVALID_KEY";
         pretty_assertions::assert_eq!(replace_cql(decoded_library), expected_result);
+
+        let decoded_library = "Patient.name = 'BBMRI_STRAT_GENDER_STRATIFIER'";
+        pretty_assertions::assert_eq!(replace_cql(decoded_library), decoded_library);
     }
 
     #[test]
@@ -414,16 +434,7 @@ mod test {
         let obfuscated_json = obfuscate_counts_mr(
             EXAMPLE_MEASURE_REPORT_BBMRI_NEW_EXTENSION,
             &mut obf_cache,
-            false,
-            1,
-            DELTA_PATIENT,
-            DELTA_SPECIMEN,
-            DELTA_DIAGNOSIS,
-            DELTA_PROCEDURES,
-            DELTA_MEDICATION_STATEMENTS,
-            DELTA_HISTO,
-            EPSILON,
-            ROUNDING_STEP,
+            &PARAMS,
         )
         .unwrap();
 
@@ -437,16 +448,7 @@ mod test {
         let obfuscated_json_2 = obfuscate_counts_mr(
             EXAMPLE_MEASURE_REPORT_BBMRI_NEW_EXTENSION,
             &mut obf_cache,
-            false,
-            1,
-            DELTA_PATIENT,
-            DELTA_SPECIMEN,
-            DELTA_DIAGNOSIS,
-            DELTA_PROCEDURES,
-            DELTA_MEDICATION_STATEMENTS,
-            DELTA_HISTO,
-            EPSILON,
-            ROUNDING_STEP,
+            &PARAMS,
         )
         .unwrap();
         pretty_assertions::assert_eq!(obfuscated_json, obfuscated_json_2);
@@ -457,21 +459,8 @@ mod test {
         let mut obf_cache = ObfCache {
             cache: HashMap::new(),
         };
-        let obfuscated_json = obfuscate_counts_mr(
-            EXAMPLE_MEASURE_REPORT_BBMRI,
-            &mut obf_cache,
-            false,
-            1,
-            DELTA_PATIENT,
-            DELTA_SPECIMEN,
-            DELTA_DIAGNOSIS,
-            DELTA_PROCEDURES,
-            DELTA_MEDICATION_STATEMENTS,
-            DELTA_HISTO,
-            EPSILON,
-            ROUNDING_STEP,
-        )
-        .unwrap();
+        let obfuscated_json =
+            obfuscate_counts_mr(EXAMPLE_MEASURE_REPORT_BBMRI, &mut obf_cache, &PARAMS).unwrap();
 
         // Check that the obfuscated JSON can be parsed and has the same structure as the original JSON
         let _: mr::MeasureReport = serde_json::from_str(&obfuscated_json).unwrap();
@@ -480,21 +469,8 @@ mod test {
         assert_ne!(obfuscated_json, EXAMPLE_MEASURE_REPORT_BBMRI);
 
         // Check that obfuscating the same JSON twice with the same obfuscation cache gives the same result
-        let obfuscated_json_2 = obfuscate_counts_mr(
-            EXAMPLE_MEASURE_REPORT_BBMRI,
-            &mut obf_cache,
-            false,
-            1,
-            DELTA_PATIENT,
-            DELTA_SPECIMEN,
-            DELTA_DIAGNOSIS,
-            DELTA_PROCEDURES,
-            DELTA_MEDICATION_STATEMENTS,
-            DELTA_HISTO,
-            EPSILON,
-            ROUNDING_STEP,
-        )
-        .unwrap();
+        let obfuscated_json_2 =
+            obfuscate_counts_mr(EXAMPLE_MEASURE_REPORT_BBMRI, &mut obf_cache, &PARAMS).unwrap();
         pretty_assertions::assert_eq!(obfuscated_json, obfuscated_json_2);
     }
 
@@ -503,21 +479,8 @@ mod test {
         let mut obf_cache = ObfCache {
             cache: HashMap::new(),
         };
-        let obfuscated_json = obfuscate_counts_mr(
-            EXAMPLE_MEASURE_REPORT_DKTK,
-            &mut obf_cache,
-            false,
-            1,
-            DELTA_PATIENT,
-            DELTA_SPECIMEN,
-            DELTA_DIAGNOSIS,
-            DELTA_PROCEDURES,
-            DELTA_MEDICATION_STATEMENTS,
-            DELTA_HISTO,
-            EPSILON,
-            ROUNDING_STEP,
-        )
-        .unwrap();
+        let obfuscated_json =
+            obfuscate_counts_mr(EXAMPLE_MEASURE_REPORT_DKTK, &mut obf_cache, &PARAMS).unwrap();
 
         // Check that the obfuscated JSON can be parsed and has the same structure as the original JSON
         let _: mr::MeasureReport = serde_json::from_str(&obfuscated_json).unwrap();
@@ -526,21 +489,8 @@ mod test {
         pretty_assertions::assert_ne!(obfuscated_json, EXAMPLE_MEASURE_REPORT_DKTK);
 
         // Check that obfuscating the same JSON twice with the same obfuscation cache gives the same result
-        let obfuscated_json_2 = obfuscate_counts_mr(
-            EXAMPLE_MEASURE_REPORT_DKTK,
-            &mut obf_cache,
-            false,
-            1,
-            DELTA_PATIENT,
-            DELTA_SPECIMEN,
-            DELTA_DIAGNOSIS,
-            DELTA_PROCEDURES,
-            DELTA_MEDICATION_STATEMENTS,
-            DELTA_HISTO,
-            EPSILON,
-            ROUNDING_STEP,
-        )
-        .unwrap();
+        let obfuscated_json_2 =
+            obfuscate_counts_mr(EXAMPLE_MEASURE_REPORT_DKTK, &mut obf_cache, &PARAMS).unwrap();
         pretty_assertions::assert_eq!(obfuscated_json, obfuscated_json_2);
     }
 
@@ -549,20 +499,8 @@ mod test {
         let mut obf_cache = ObfCache {
             cache: HashMap::new(),
         };
-        let obfuscated_json = obfuscate_counts_mr(
-            EXAMPLE_MEASURE_REPORT_EXLIQUID,
-            &mut obf_cache,
-            false,
-            1,
-            DELTA_PATIENT,
-            DELTA_SPECIMEN,
-            DELTA_DIAGNOSIS,
-            DELTA_PROCEDURES,
-            DELTA_MEDICATION_STATEMENTS,
-            DELTA_HISTO,
-            EPSILON,
-            ROUNDING_STEP,
-        );
+        let obfuscated_json =
+            obfuscate_counts_mr(EXAMPLE_MEASURE_REPORT_EXLIQUID, &mut obf_cache, &PARAMS);
 
         pretty_assertions::assert_eq!(
             obfuscated_json.unwrap_err().to_string(),
@@ -0,0 +1,539 @@
+use std::fmt;
+
+use tracing::warn;
+
+use crate::ast;
+use crate::errors::FocusError;
+use crate::projects::{self, CriterionRole, Project};
+use crate::units;
+
+/// Deeper ASTs are rejected, Lens never builds more than a handful of levels
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The AST can't be turned into a query that means what was asked for
+    Error,
+    /// The AST works, but likely not as intended
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    /// JSON pointer to the offending node, e.g. /ast/children/0/children/2
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{} at {}: {}", severity, self.path, self.message)
+    }
+}
+
+/// Checks the AST for problems before any query is built from it, criteria are only checked against the project's snippets if there is one
+pub fn validate(ast: &ast::Ast, project: Option<&Project>) -> Vec<Problem> {
+    let mut validator = Validator {
+        project,
+        problems: Vec::new(),
+    };
+    validator.operation(&ast.ast, "/ast", 1, true);
+    validator.problems
+}
+
+/// Logs the warnings and fails with all errors found by [`validate`]
+pub fn ensure_valid(ast: &ast::Ast, project: Option<&Project>) -> Result<(), FocusError> {
+    let (errors, warnings): (Vec<Problem>, Vec<Problem>) = validate(ast, project)
+        .into_iter()
+        .partition(|problem| problem.severity == Severity::Error);
+
+    for warning in &warnings {
+        warn!("AST {}: {}", ast.id, warning);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(FocusError::AstInvalid(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>()
+                .join("; "),
+        ))
+    }
+}
+
+/// Validates the AST and normalizes it, so that equivalent ASTs generate the same query text
+pub fn validated(ast: ast::Ast, project: Option<&Project>) -> Result<ast::Ast, FocusError> {
+    ensure_valid(&ast, project)?;
+    Ok(ast::Ast {
+        ast: ast.ast.normalize(),
+        id: ast.id,
+    })
+}
+
+struct Validator<'a> {
+    project: Option<&'a Project>,
+    problems: Vec<Problem>,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, severity: Severity, path: &str, message: String) {
+        self.problems.push(Problem {
+            severity,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn operation(&mut self, operation: &ast::Operation, path: &str, depth: usize, root: bool) {
+        if depth > MAX_DEPTH {
+            self.report(
+                Severity::Error,
+                path,
+                format!("AST is nested deeper than {} levels", MAX_DEPTH),
+            );
+            return;
+        }
+
+        // NOT over groups without criteria would negate everything, which is never what was meant
+        if operation.operand == ast::Operand::Not
+            && operation.children.iter().all(ast::Child::is_empty_group)
+        {
+            self.report(
+                Severity::Error,
+                path,
+                "Operator NOT needs at least one criterion".into(),
+            );
+        } else if operation.children.is_empty() && !root {
+            // an empty root is how Lens asks for everything
+            self.report(
+                Severity::Warning,
+                path,
+                format!(
+                    "Empty {:?} group is ignored and matches everything",
+                    operation.operand
+                ),
+            );
+        }
+
+        for (index, child) in operation.children.iter().enumerate() {
+            let path = format!("{}/children/{}", path, index);
+            match child {
+                ast::Child::Operation(operation) => {
+                    self.operation(operation, &path, depth + 1, false)
+                }
+                ast::Child::Condition(condition) => self.condition(condition, &path),
+                ast::Child::Temporal(temporal) => self.temporal(temporal, &path),
+                ast::Child::Entity(entity) => self.entity(entity, &path),
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &ast::Condition, path: &str) {
+        // criteria of other projects are checked against those, without a project there is nothing to check against
+        if self.project.is_some() {
+            match projects::namespaced(&condition.key) {
+                Ok(Some((namespace_project, key))) => {
+                    let mut validator = Validator {
+                        project: Some(&namespace_project),
+                        problems: std::mem::take(&mut self.problems),
+                    };
+                    validator.condition(
+                        &ast::Condition {
+                            key: key.to_string(),
+                            ..condition.clone()
+                        },
+                        path,
+                    );
+                    self.problems = validator.problems;
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.report(Severity::Error, path, e.to_string());
+                    return;
+                }
+            }
+        }
+
+        self.known_criterion(&condition.key, CriterionRole::Query, "", path);
+        self.operator_value(condition, path);
+        self.unit(condition, path);
+        self.descendants(condition, path);
+
+        match condition.count {
+            Some(0) => self.report(
+                Severity::Warning,
+                path,
+                format!("Count 0 for {} matches everything", condition.key),
+            ),
+            Some(_) => {
+                self.known_criterion(&condition.key, CriterionRole::Retrieve, " for counts", path);
+                self.list_value(condition, "counts", path);
+            }
+            None => {}
+        }
+    }
+
+    fn temporal(&mut self, temporal: &ast::Temporal, path: &str) {
+        if temporal.relation == ast::TemporalRelation::Within && temporal.days.is_none() {
+            self.report(
+                Severity::Error,
+                path,
+                "Temporal relation WITHIN needs a number of days".into(),
+            );
+        }
+
+        for (condition, path) in [
+            (&temporal.anchor, format!("{}/anchor", path)),
+            (&temporal.event, format!("{}/event", path)),
+        ] {
+            self.known_criterion(
+                &condition.key,
+                CriterionRole::Event,
+                " for temporal relations",
+                &path,
+            );
+            self.list_value(condition, "temporal relations", &path);
+            if condition.count.is_some() {
+                self.report(
+                    Severity::Error,
+                    &path,
+                    "Counts are not supported in temporal relations".into(),
+                );
+            }
+        }
+    }
+
+    fn entity(&mut self, entity: &ast::Entity, path: &str) {
+        if entity.children.is_empty() {
+            self.report(
+                Severity::Error,
+                path,
+                format!("Entity {} needs at least one condition", entity.entity),
+            );
+        }
+        if let Some(project) = self.project {
+            if !project
+                .get_entity_sources()
+                .contains_key(entity.entity.as_str())
+            {
+                self.report(
+                    Severity::Error,
+                    path,
+                    format!("Unknown entity {}", entity.entity),
+                );
+            }
+        }
+
+        for (index, condition) in entity.children.iter().enumerate() {
+            let path = format!("{}/children/{}", path, index);
+            if let Some(project) = self.project {
+                if !project
+                    .get_entity_snippets()
+                    .contains_key(&(entity.entity.as_str(), condition.key.as_str()))
+                {
+                    self.report(
+                        Severity::Error,
+                        &path,
+                        format!(
+                            "Unknown criterion {} for entity {}",
+                            condition.key, entity.entity
+                        ),
+                    );
+                }
+            }
+            self.operator_value(condition, &path);
+            if condition.count.is_some() {
+                self.report(
+                    Severity::Error,
+                    &path,
+                    format!(
+                        "Counts are not supported for conditions on entity {}",
+                        entity.entity
+                    ),
+                );
+            }
+        }
+    }
+
+    fn descendants(&mut self, condition: &ast::Condition, path: &str) {
+        if condition.descendants
+            && !matches!(
+                condition.type_,
+                ast::ConditionType::Equals | ast::ConditionType::In
+            )
+        {
+            self.report(
+                Severity::Error,
+                path,
+                format!(
+                    "Descendants can only be matched for EQUALS and IN, not for {:?}",
+                    condition.type_
+                ),
+            );
+        }
+    }
+
+    fn unit(&mut self, condition: &ast::Condition, path: &str) {
+        let Some(unit) = &condition.unit else {
+            return;
+        };
+        if !matches!(
+            condition.value,
+            ast::ConditionValue::Number(_) | ast::ConditionValue::NumRange(_)
+        ) {
+            self.report(
+                Severity::Error,
+                path,
+                format!("Unit {} can only be given for numbers", unit),
+            );
+            return;
+        }
+        if !units::is_known(unit) {
+            self.report(Severity::Error, path, format!("Unknown unit {}", unit));
+            return;
+        }
+        if let Some(project) = self.project {
+            match project.get_criterion_units().get(condition.key.as_str()) {
+                Some(expected) => {
+                    if let Err(e) = units::convert(0., unit, expected) {
+                        self.report(Severity::Error, path, e.to_string());
+                    }
+                }
+                None => self.report(
+                    Severity::Error,
+                    path,
+                    format!(
+                        "Criterion {} has no unit, so {} can't be used",
+                        condition.key, unit
+                    ),
+                ),
+            }
+        }
+    }
+
+    fn known_criterion(&mut self, key: &str, role: CriterionRole, usage: &str, path: &str) {
+        if let Some(project) = self.project {
+            if !project.get_cql_snippets().contains_key(&(key, role)) {
+                self.report(
+                    Severity::Error,
+                    path,
+                    format!("Unknown criterion {}{}", key, usage),
+                );
+            }
+        }
+    }
+
+    /// Criteria turned into lists of resources or dates only take single strings or string arrays
+    fn list_value(&mut self, condition: &ast::Condition, usage: &str, path: &str) {
+        match (&condition.type_, &condition.value) {
+            (ast::ConditionType::Equals, ast::ConditionValue::String(_))
+            | (ast::ConditionType::In, ast::ConditionValue::StringArray(_)) => {}
+            (type_, value) => self.report(
+                Severity::Error,
+                path,
+                format!(
+                    "Only EQUALS with a string and IN with a string array are supported for {}, not {:?} with {:?}",
+                    usage, type_, value
+                ),
+            ),
+        }
+    }
+
+    fn operator_value(&mut self, condition: &ast::Condition, path: &str) {
+        use ast::ConditionType as Type;
+        use ast::ConditionValue as Value;
+
+        // the combinations are those the query builders of the projects support, other endpoints get the AST as is
+        let unsupported = if self.project.is_some() {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+
+        match (&condition.type_, &condition.value) {
+            (Type::Between, Value::NumRange(range)) => {
+                if range.min > range.max {
+                    self.report(
+                        Severity::Error,
+                        path,
+                        format!(
+                            "Range of {} from {} to {} is empty",
+                            condition.key, range.min, range.max
+                        ),
+                    );
+                }
+            }
+            (Type::Between, Value::DateRange(range)) => match (range.min, range.max) {
+                (Some(min), Some(max)) if min.first_day() > max.last_day() => self.report(
+                    Severity::Error,
+                    path,
+                    format!(
+                        "Range of {} from {} to {} is empty",
+                        condition.key, min, max
+                    ),
+                ),
+                (None, None) => self.report(
+                    Severity::Error,
+                    path,
+                    format!("Range of {} has neither a min nor a max", condition.key),
+                ),
+                _ => {}
+            },
+            (Type::LowerThan | Type::GreaterThan, Value::Number(_)) => {}
+            (Type::LowerThan | Type::GreaterThan, Value::String(date)) => {
+                if let Err(e) = date.parse::<ast::AstDate>() {
+                    self.report(unsupported, path, e.to_string());
+                }
+            }
+            (Type::In, Value::StringArray(values))
+            | (Type::Contains, Value::StringArray(values)) => {
+                if values.is_empty() {
+                    self.report(
+                        Severity::Error,
+                        path,
+                        format!("Operator {:?} needs at least one value", condition.type_),
+                    );
+                }
+            }
+            (Type::Equals | Type::NotEquals, Value::String(_)) => {}
+            (type_, value) => self.report(
+                unsupported,
+                path,
+                format!("Operator {:?} can't be used with {:?}", type_, value),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VALID: &str = r#"{"ast":{"operand":"OR","children":[{"operand":"AND","children":[{"key":"gender","type":"EQUALS","value":"male"},{"key":"sample_kind","type":"IN","value":["blood-serum","tissue-ffpe"]},{"key":"donor_age","type":"BETWEEN","value":{"min":20,"max":40}}]}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const EMPTY: &str =
+        r#"{"ast":{"operand":"OR","children":[]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    const PROBLEMS: &str = r#"{"ast":{"operand":"AND","children":[{"key":"shoe_size","type":"EQUALS","value":"42"},{"operand":"OR","children":[{"key":"gender","type":"IN","value":"male"},{"key":"donor_age","type":"BETWEEN","value":{"min":70,"max":30}}]},{"operand":"OR","children":[]},{"key":"sampling_date","type":"BETWEEN","value":{"min":"2020-12-31","max":"2020-01-01"}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_valid() {
+        let ast: ast::Ast = serde_json::from_str(VALID).unwrap();
+        pretty_assertions::assert_eq!(validate(&ast, Some(&Project::Bbmri)), vec![]);
+        assert!(ensure_valid(&ast, Some(&Project::Bbmri)).is_ok());
+
+        let ast: ast::Ast = serde_json::from_str(EMPTY).unwrap();
+        pretty_assertions::assert_eq!(validate(&ast, Some(&Project::Bbmri)), vec![]);
+    }
+
+    #[test]
+    fn test_problems() {
+        let ast: ast::Ast = serde_json::from_str(PROBLEMS).unwrap();
+        let problems = validate(&ast, Some(&Project::Bbmri));
+
+        pretty_assertions::assert_eq!(
+            problems
+                .iter()
+                .map(|problem| (problem.severity, problem.path.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Severity::Error, "/ast/children/0"),
+                (Severity::Error, "/ast/children/1/children/0"),
+                (Severity::Error, "/ast/children/1/children/1"),
+                (Severity::Warning, "/ast/children/2"),
+                (Severity::Error, "/ast/children/3"),
+            ]
+        );
+        pretty_assertions::assert_eq!(
+            problems[0].to_string(),
+            "error at /ast/children/0: Unknown criterion shoe_size"
+        );
+
+        // without a project, criteria can't be checked and operators only get warnings
+        pretty_assertions::assert_eq!(validate(&ast, None).len(), 4);
+        pretty_assertions::assert_eq!(validate(&ast, None)[0].severity, Severity::Warning);
+
+        assert!(ensure_valid(&ast, Some(&Project::Bbmri)).is_err());
+    }
+
+    const OPEN_DATE_RANGE: &str = r#"{"ast":{"operand":"AND","children":[{"key":"sampling_date","type":"BETWEEN","value":{"min":"","max":""}}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_open_date_range() {
+        let ast: ast::Ast = serde_json::from_str(OPEN_DATE_RANGE).unwrap();
+        pretty_assertions::assert_eq!(
+            validate(&ast, None)[0].to_string(),
+            "error at /ast/children/0: Range of sampling_date has neither a min nor a max"
+        );
+    }
+
+    const OMOP_OPERATORS: &str = r#"{"ast":{"operand":"AND","children":[{"key":"smoker","type":"EQUALS","value":true},{"key":"tumor_size","type":"EQUALS","value":3}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#;
+
+    #[test]
+    fn test_operators_without_project() {
+        // OMOP gets the AST as is, so operators the query builders don't support are fine there
+        let ast: ast::Ast = serde_json::from_str(OMOP_OPERATORS).unwrap();
+        assert!(ensure_valid(&ast, None).is_ok());
+        assert!(ensure_valid(&ast, Some(&Project::Bbmri)).is_err());
+    }
+
+    #[test]
+    fn test_not_over_empty_groups() {
+        for json in [
+            r#"{"operand":"NOT","children":[{"operand":"AND","children":[]}]}"#,
+            r#"{"operand":"AND","children":[{"key":"gender","type":"EQUALS","value":"male"},{"operand":"NOT","children":[{"operand":"OR","children":[{"operand":"AND","children":[]}]}]}]}"#,
+        ] {
+            let ast: ast::Ast = serde_json::from_str(&format!(
+                r#"{{"ast":{},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}}"#,
+                json
+            ))
+            .unwrap();
+            assert!(ensure_valid(&ast, Some(&Project::Bbmri)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_units() {
+        let ast: ast::Ast = serde_json::from_str(r#"{"ast":{"operand":"AND","children":[{"key":"body_weight","type":"BETWEEN","value":{"min":150,"max":200},"unit":"[lb_av]"},{"key":"bmi","type":"LOWER_THAN","value":30,"unit":"kg"},{"key":"donor_age","type":"BETWEEN","value":{"min":20,"max":40},"unit":"kg"},{"key":"body_weight","type":"BETWEEN","value":{"min":50,"max":70},"unit":"stone"}]},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}"#).unwrap();
+
+        pretty_assertions::assert_eq!(
+            validate(&ast, Some(&Project::Bbmri))
+                .iter()
+                .map(|problem| problem.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["/ast/children/1", "/ast/children/2", "/ast/children/3"]
+        );
+        pretty_assertions::assert_eq!(validate(&ast, None).len(), 1);
+    }
+
+    #[test]
+    fn test_depth() {
+        let mut json = r#"{"key":"gender","type":"EQUALS","value":"male"}"#.to_string();
+        for _ in 0..MAX_DEPTH {
+            json = format!(r#"{{"operand":"AND","children":[{}]}}"#, json);
+        }
+        let ast: ast::Ast = serde_json::from_str(&format!(
+            r#"{{"ast":{},"id":"a6f1ccf3-ebf1-424f-9d69-4e5d135f2340"}}"#,
+            json
+        ))
+        .unwrap();
+        pretty_assertions::assert_eq!(validate(&ast, Some(&Project::Bbmri)), vec![]);
+
+        let ast = ast::Ast {
+            ast: ast::Operation {
+                operand: ast::Operand::Or,
+                children: vec![ast::Child::Operation(ast.ast)],
+            },
+            id: ast.id,
+        };
+        pretty_assertions::assert_eq!(validate(&ast, Some(&Project::Bbmri)).len(), 1);
+    }
+}